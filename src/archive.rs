@@ -0,0 +1,151 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path},
+};
+
+use crate::YomiDictError;
+
+const ZIP_MAGIC: &[u8] = b"PK";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Container format of a dictionary, detected from the first bytes of the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(ZIP_MAGIC) {
+            Some(Self::Zip)
+        } else if header.starts_with(GZIP_MAGIC) {
+            Some(Self::TarGz)
+        } else if header.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len())
+            == Some(TAR_MAGIC)
+        {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read access to the files of a dictionary archive, independent of the container format
+pub enum Archive<R: Read + Seek> {
+    Zip(zip::ZipArchive<R>),
+    /// Tar archives can't be accessed randomly, so their files are read into memory up front
+    #[cfg(feature = "tar")]
+    Tar(Vec<(String, Vec<u8>)>),
+}
+
+impl<R: Read + Seek> Archive<R> {
+    pub fn new(mut reader: R) -> Result<Self, YomiDictError> {
+        let mut header = Vec::with_capacity(TAR_MAGIC_OFFSET + TAR_MAGIC.len());
+        (&mut reader)
+            .take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+            .read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        match ArchiveKind::detect(&header) {
+            // Unknown headers are handed to the zip reader, which gives the most helpful error
+            Some(ArchiveKind::Zip) | None => Ok(Self::Zip(zip::ZipArchive::new(reader)?)),
+            #[cfg(feature = "tar")]
+            Some(ArchiveKind::Tar) => Self::read_tar(reader),
+            #[cfg(feature = "tar")]
+            Some(ArchiveKind::TarGz) => Self::read_tar(flate2::read::GzDecoder::new(reader)),
+            #[cfg(not(feature = "tar"))]
+            Some(ArchiveKind::Tar | ArchiveKind::TarGz) => Err(YomiDictError::UnsupportedArchive(
+                "Tar archives require the `tar` feature",
+            )),
+        }
+    }
+
+    #[cfg(feature = "tar")]
+    fn read_tar(reader: impl Read) -> Result<Self, YomiDictError> {
+        let mut archive = tar::Archive::new(reader);
+        let mut files = vec![];
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let Some(name) = normalize_name(&entry.path()?) else {
+                continue;
+            };
+
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            files.push((name, data));
+        }
+
+        Ok(Self::Tar(files))
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Zip(archive) => archive.len(),
+            #[cfg(feature = "tar")]
+            Self::Tar(files) => files.len(),
+        }
+    }
+
+    /// Name and content of the file at the given position.
+    /// The name is `None` for entries that shouldn't be read, such as directories or unsafe paths.
+    pub fn by_index(
+        &mut self,
+        index: usize,
+    ) -> Result<(Option<String>, Box<dyn Read + '_>), YomiDictError> {
+        match self {
+            Self::Zip(archive) => {
+                let file = archive.by_index(index)?;
+                let name = file.enclosed_name().and_then(normalize_name);
+                Ok((name, Box::new(file)))
+            }
+            #[cfg(feature = "tar")]
+            Self::Tar(files) => {
+                let (name, data) = &files[index];
+                Ok((Some(name.clone()), Box::new(data.as_slice())))
+            }
+        }
+    }
+
+    /// The dictionary's `index.json`
+    pub fn index_json(&mut self) -> Result<Box<dyn Read + '_>, YomiDictError> {
+        const INDEX_JSON: &str = "index.json";
+
+        match self {
+            Self::Zip(archive) => match archive.by_name(INDEX_JSON) {
+                Ok(file) => Ok(Box::new(file)),
+                Err(zip::result::ZipError::FileNotFound) => Err(YomiDictError::IndexNotFound),
+                Err(e) => Err(e.into()),
+            },
+            #[cfg(feature = "tar")]
+            Self::Tar(files) => files
+                .iter()
+                .find(|(name, _)| name == INDEX_JSON)
+                .map(|(_, data)| Box::new(data.as_slice()) as Box<dyn Read>)
+                .ok_or(YomiDictError::IndexNotFound),
+        }
+    }
+}
+
+/// Turns `./term_bank_1.json` into `term_bank_1.json`, rejecting paths that leave the archive
+fn normalize_name(path: &Path) -> Option<String> {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::CurDir => continue,
+            _ => return None,
+        }
+    }
+
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
@@ -4,11 +4,17 @@ use std::pin::Pin;
 use async_trait::async_trait;
 use futures::{future::join_all, Future};
 
+use wana_kana::ConvertJapanese;
+
 use crate::{
     deinflect::Reasons,
+    kanji_bank::Kanji,
+    search::{glossary_tokens, rank_glossary_matches, SearchMode, TermPattern},
+    tag_bank::Tag,
+    term_meta_bank::TermMeta,
     terms_bank::Term,
-    translator::{get_grouped_terms, DictEntries},
-    Dict, YomiDictError,
+    translator::{get_terms, parse_text, DictEntries, FindTermsOptions, ResultMode, Segment},
+    Dict, Index, YomiDictError,
 };
 
 type StepFuture<'a> = dyn Future<Output = Result<usize, YomiDictError>> + 'a;
@@ -17,6 +23,16 @@ pub struct DictInsertionSteps<'a> {
     pub steps: Vec<Pin<Box<StepFuture<'a>>>>,
 }
 
+/// A dictionary as stored in the database
+#[derive(Debug, Clone)]
+pub struct StoredDict {
+    pub id: u8,
+    pub index: Index,
+    /// Whether frequency or pitch data was added with the dictionary.
+    /// Dictionaries added before this was recorded are assumed to have some.
+    pub has_term_meta: bool,
+}
+
 #[async_trait(?Send)]
 pub trait DBImpl {
     async fn add_dict_stepwise(&self, dict: Dict) -> Result<DictInsertionSteps<'_>, YomiDictError>;
@@ -24,6 +40,31 @@ pub trait DBImpl {
         &self,
         term_list: impl IntoIterator<Item = &str>,
     ) -> Result<Vec<Term>, YomiDictError>;
+    async fn get_kanji_by_reading(
+        &self,
+        onyomi: &str,
+        kunyomi: &str,
+    ) -> Result<Vec<Kanji>, YomiDictError>;
+    async fn get_tags(&self, dict_id: u8) -> Result<Vec<Tag>, YomiDictError>;
+    async fn get_glossary_matches(
+        &self,
+        tokens: impl IntoIterator<Item = &str>,
+    ) -> Result<Vec<Term>, YomiDictError>;
+    /// Terms whose expression or reading start with the prefix, or end with it if `reversed`.
+    /// The prefix is never empty.
+    async fn get_prefix_matches(
+        &self,
+        prefix: &str,
+        reversed: bool,
+    ) -> Result<Vec<Term>, YomiDictError>;
+    async fn get_by_sequence(&self, dict_id: u8, sequence: u32)
+        -> Result<Vec<Term>, YomiDictError>;
+    async fn get_term_meta(
+        &self,
+        expressions: impl IntoIterator<Item = &str>,
+    ) -> Result<Vec<TermMeta>, YomiDictError>;
+    /// All dictionaries with their ids
+    async fn get_dictionaries(&self) -> Result<Vec<StoredDict>, YomiDictError>;
 }
 
 #[async_trait(?Send)]
@@ -34,7 +75,39 @@ pub trait DB {
         text: &str,
         reasons: &Reasons,
     ) -> Result<Vec<DictEntries>, YomiDictError>;
+    async fn find_terms_merged(
+        &self,
+        text: &str,
+        reasons: &Reasons,
+        main_dict: u8,
+    ) -> Result<Vec<DictEntries>, YomiDictError>;
+    async fn find_terms_with_options(
+        &self,
+        text: &str,
+        reasons: &Reasons,
+        options: &FindTermsOptions,
+    ) -> Result<Vec<DictEntries>, YomiDictError>;
+    async fn parse_text(
+        &self,
+        text: &str,
+        reasons: &Reasons,
+        options: &FindTermsOptions,
+    ) -> Result<Vec<Segment>, YomiDictError>;
     async fn add_dict_stepwise(&self, dict: Dict) -> Result<DictInsertionSteps<'_>, YomiDictError>;
+    async fn find_kanji_by_reading(&self, reading: &str) -> Result<Vec<Kanji>, YomiDictError>;
+    async fn find_tags(&self, dict_id: u8) -> Result<Vec<Tag>, YomiDictError>;
+    async fn search_glossary(&self, query: &str) -> Result<Vec<Term>, YomiDictError>;
+    async fn search_terms(
+        &self,
+        pattern: &str,
+        mode: SearchMode,
+    ) -> Result<Vec<Term>, YomiDictError>;
+    async fn find_by_sequence(
+        &self,
+        dict_id: u8,
+        sequence: u32,
+    ) -> Result<Vec<Term>, YomiDictError>;
+    async fn find_dictionaries(&self) -> Result<Vec<(u8, Index)>, YomiDictError>;
 }
 
 #[async_trait(?Send)]
@@ -68,6 +141,113 @@ impl<T: DBImpl> DB for T {
         text: &str,
         reasons: &Reasons,
     ) -> Result<Vec<DictEntries>, YomiDictError> {
-        get_grouped_terms(text, reasons, self).await
+        self.find_terms_with_options(text, reasons, &FindTermsOptions::default())
+            .await
+    }
+
+    /// Like [`DB::find_terms`], but all terms of the main dictionary sharing a sequence number
+    /// form one result, e.g. every spelling of a JMdict entry.
+    /// Definitions of other dictionaries for any of the entry's forms are added to it.
+    /// Results are grouped as in [`DB::find_terms`] if the main dictionary isn't sequenced,
+    /// see [`crate::Index::sequenced`].
+    async fn find_terms_merged(
+        &self,
+        text: &str,
+        reasons: &Reasons,
+        main_dict: u8,
+    ) -> Result<Vec<DictEntries>, YomiDictError> {
+        let options = FindTermsOptions {
+            mode: ResultMode::Merge { main_dict },
+            ..Default::default()
+        };
+        self.find_terms_with_options(text, reasons, &options).await
+    }
+
+    /// Like [`DB::find_terms`], with the results combined as selected by the options
+    async fn find_terms_with_options(
+        &self,
+        text: &str,
+        reasons: &Reasons,
+        options: &FindTermsOptions,
+    ) -> Result<Vec<DictEntries>, YomiDictError> {
+        let dictionaries = self.get_dictionaries().await?;
+        get_terms(text, reasons, options, &dictionaries, self).await
+    }
+
+    /// Split the whole text into words, taking the longest match at each position.
+    /// Unknown chars become segments without results.
+    async fn parse_text(
+        &self,
+        text: &str,
+        reasons: &Reasons,
+        options: &FindTermsOptions,
+    ) -> Result<Vec<Segment>, YomiDictError> {
+        parse_text(text, reasons, options, self).await
+    }
+
+    /// Give all kanji that have the reading as either on or kun reading.
+    /// Kun readings match both with and without okurigana, e.g. `たべる` and `た` for `食`.
+    async fn find_kanji_by_reading(&self, reading: &str) -> Result<Vec<Kanji>, YomiDictError> {
+        self.get_kanji_by_reading(&reading.to_katakana(), &reading.to_hiragana())
+            .await
+    }
+
+    /// Give all tags of a dictionary, e.g. to resolve [`Kanji::grouped_stats`]
+    async fn find_tags(&self, dict_id: u8) -> Result<Vec<Tag>, YomiDictError> {
+        self.get_tags(dict_id).await
+    }
+
+    /// Give all terms with a definition containing words of the query, e.g. `to hear`.
+    /// The best matches come first.
+    async fn search_glossary(&self, query: &str) -> Result<Vec<Term>, YomiDictError> {
+        let tokens = glossary_tokens(query);
+        let terms = self
+            .get_glossary_matches(tokens.iter().map(String::as_str))
+            .await?;
+
+        Ok(rank_glossary_matches(query, terms))
+    }
+
+    /// Give all terms whose expression or reading matches the pattern, shortest first.
+    /// Wildcard patterns starting with a wildcard can only use the index if they end with
+    /// a literal, e.g. `*ness`.
+    ///
+    /// # Errors
+    ///
+    /// Patterns without a literal start or end, like `*べ*`, are rejected with
+    /// [`YomiDictError::UnboundedPattern`] instead of going through every term.
+    async fn search_terms(
+        &self,
+        pattern: &str,
+        mode: SearchMode,
+    ) -> Result<Vec<Term>, YomiDictError> {
+        let term_pattern = TermPattern::new(pattern, mode);
+        let Some((key, reversed)) = term_pattern.range_key() else {
+            return Err(YomiDictError::UnboundedPattern(pattern.to_owned()));
+        };
+
+        let terms = self.get_prefix_matches(&key, reversed).await?;
+
+        Ok(term_pattern.filter_matches(terms))
+    }
+
+    /// Give all terms of a dictionary sharing the sequence number, e.g. every sense and spelling
+    /// of a JMdict entry. Only meaningful for dictionaries with [`crate::Index::sequenced`] set.
+    async fn find_by_sequence(
+        &self,
+        dict_id: u8,
+        sequence: u32,
+    ) -> Result<Vec<Term>, YomiDictError> {
+        self.get_by_sequence(dict_id, sequence).await
+    }
+
+    /// Give the index of every dictionary with the id its entries are stored under
+    async fn find_dictionaries(&self) -> Result<Vec<(u8, Index)>, YomiDictError> {
+        Ok(self
+            .get_dictionaries()
+            .await?
+            .into_iter()
+            .map(|d| (d.id, d.index))
+            .collect())
     }
 }
@@ -5,51 +5,135 @@ use async_trait::async_trait;
 use futures::{future::join_all, Future};
 use itertools::Itertools;
 use rexie::{Index, KeyRange, ObjectStore, Rexie};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use wasm_bindgen::JsValue;
 
 use crate::{
-    db::{DBImpl, DictInsertionSteps},
+    db::{DBImpl, DictInsertionSteps, StoredDict},
     dict_item::DictItem,
+    kanji_bank::Kanji,
+    tag_bank::Tag,
+    term_meta_bank::TermMeta,
     terms_bank::Term,
-    Dict, YomiDictError,
+    Dict, Index as DictIndex, YomiDictError,
 };
 
 pub struct IndexedDB {
     rexie: Rexie,
 }
 
+/// What's stored for a dictionary: its index and what was added with it
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DictRecord<'a> {
+    #[serde(flatten)]
+    index: &'a DictIndex,
+    has_term_meta: bool,
+}
+
 #[derive(Deserialize)]
 pub struct IdObject {
     id: u32,
 }
 
+/// Deserialize the objects of several queries, skipping objects already returned by another query
+fn unique_objects<T: DeserializeOwned>(
+    results: Vec<Vec<(JsValue, JsValue)>>,
+) -> Result<Vec<T>, YomiDictError> {
+    Ok(results
+        .into_iter()
+        .flatten()
+        .map(|(_, obj)| obj)
+        .unique_by(|jobj| {
+            serde_wasm_bindgen::from_value::<IdObject>(jobj.clone())
+                .map(|obj| obj.id)
+                .unwrap_or(0)
+        })
+        .map(serde_wasm_bindgen::from_value)
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Version 1 stored kanji readings as space separated strings without index keys
+const KANJI_READING_LISTS_VERSION: f64 = 2.0;
+
 impl IndexedDB {
     pub async fn new(name: &str) -> Result<Self, YomiDictError> {
+        // Opening without a version doesn't upgrade, so this is the version the data was written with
+        let previous = Rexie::builder(name).build().await?;
+        let migrate_kanji = previous.version() < KANJI_READING_LISTS_VERSION
+            && previous.store_names().iter().any(|s| s == "kanji");
+        previous.close();
+
         let rexie = Rexie::builder(name)
-            .version(1)
+            .version(7)
             .add_object_store(
                 ObjectStore::new("dictionaries")
                     .key_path("id")
                     .auto_increment(true)
                     .add_index(Index::new("title", "title")),
             )
-            .add_object_store(ObjectStore::new("tags").key_path("id").auto_increment(true))
+            .add_object_store(
+                ObjectStore::new("tags")
+                    .key_path("id")
+                    .auto_increment(true)
+                    .add_index(Index::new("dict_id", "dict_id")),
+            )
             .add_object_store(
                 ObjectStore::new("terms")
                     .key_path("id")
                     .auto_increment(true)
                     .add_index(Index::new("expression", "expression"))
-                    .add_index(Index::new("reading", "reading")),
+                    .add_index(Index::new("reading", "reading"))
+                    .add_index(Index::new("glossary", "glossary_tokens").multi_entry(true))
+                    .add_index(Index::new("expression_reverse", "expression_reverse"))
+                    .add_index(Index::new("reading_reverse", "reading_reverse"))
+                    .add_index(Index::new_array("sequence", ["dict_id", "sequence"])),
             )
             .add_object_store(
                 ObjectStore::new("kanji")
                     .key_path("id")
-                    .auto_increment(true),
+                    .auto_increment(true)
+                    .add_index(Index::new("onyomi", "onyomi").multi_entry(true))
+                    .add_index(Index::new("kunyomi", "kunyomi_keys").multi_entry(true)),
+            )
+            .add_object_store(
+                ObjectStore::new("term_meta")
+                    .key_path("id")
+                    .auto_increment(true)
+                    .add_index(Index::new("expression", "expression")),
             )
             .build()
             .await?;
 
-        Ok(Self { rexie })
+        let db = Self { rexie };
+        if migrate_kanji {
+            db.migrate_kanji_readings().await?;
+        }
+
+        Ok(db)
+    }
+
+    /// Rewrite kanji stored with string readings, so they get reading lists and index keys
+    async fn migrate_kanji_readings(&self) -> Result<(), YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["kanji"], rexie::TransactionMode::ReadWrite)?;
+
+        let store = transaction.store("kanji")?;
+
+        for (key, value) in store.get_all(None, None, None, None).await? {
+            let mut kanji: Kanji = serde_wasm_bindgen::from_value(value)?;
+            kanji.build_index_keys();
+
+            store.delete(&key).await?;
+            store
+                .add(&serde_wasm_bindgen::to_value(&kanji)?, None)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
     }
 
     fn create_insertion_future<'a>(
@@ -69,6 +153,7 @@ impl IndexedDB {
 
             for mut item in items {
                 item.set_dict_id(dict_id);
+                item.build_index_keys();
 
                 store
                     .add(&serde_wasm_bindgen::to_value(&item)?, None)
@@ -107,15 +192,24 @@ impl DBImpl for IndexedDB {
             }); // TODO duplicate error?
         }
 
+        let record = DictRecord {
+            index: &dict.index,
+            has_term_meta: !dict.term_meta.is_empty(),
+        };
+        // The index keeps unknown fields in a flattened map, which would otherwise become a JS `Map`
         let dict_id = dictionaries
-            .put(&serde_wasm_bindgen::to_value(&dict.index)?, None)
+            .put(
+                &record.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?,
+                None,
+            )
             .await?;
 
         transaction.commit().await?;
 
         let dict_id: u8 = serde_wasm_bindgen::from_value(dict_id)?;
 
-        let total_count = dict.tags.len() + dict.terms.len() + dict.kanji.len();
+        let total_count =
+            dict.tags.len() + dict.terms.len() + dict.kanji.len() + dict.term_meta.len();
         let mut steps = Vec::new();
 
         steps.extend(
@@ -123,7 +217,7 @@ impl DBImpl for IndexedDB {
                 .into_iter()
                 .chunks(TRANSACTION_SIZE)
                 .into_iter()
-                .map(|c| (self.create_insertion_future("tags", dict_id, c.collect_vec()))),
+                .map(|c| self.create_insertion_future("tags", dict_id, c.collect_vec())),
         );
 
         steps.extend(
@@ -131,7 +225,7 @@ impl DBImpl for IndexedDB {
                 .into_iter()
                 .chunks(TRANSACTION_SIZE)
                 .into_iter()
-                .map(|c| (self.create_insertion_future("terms", dict_id, c.collect_vec()))),
+                .map(|c| self.create_insertion_future("terms", dict_id, c.collect_vec())),
         );
 
         steps.extend(
@@ -139,7 +233,15 @@ impl DBImpl for IndexedDB {
                 .into_iter()
                 .chunks(TRANSACTION_SIZE)
                 .into_iter()
-                .map(|c| (self.create_insertion_future("kanji", dict_id, c.collect_vec()))),
+                .map(|c| self.create_insertion_future("kanji", dict_id, c.collect_vec())),
+        );
+
+        steps.extend(
+            dict.term_meta
+                .into_iter()
+                .chunks(TRANSACTION_SIZE)
+                .into_iter()
+                .map(|c| self.create_insertion_future("term_meta", dict_id, c.collect_vec())),
         );
 
         Ok(DictInsertionSteps { total_count, steps })
@@ -155,7 +257,7 @@ impl DBImpl for IndexedDB {
 
         let terms = transaction.store("terms")?;
 
-        let indices = vec![terms.index("expression")?, terms.index("reading")?];
+        let indices = [terms.index("expression")?, terms.index("reading")?];
 
         let term_list = term_list
             .into_iter()
@@ -189,4 +291,224 @@ impl DBImpl for IndexedDB {
 
         Ok(terms)
     }
+
+    async fn get_kanji_by_reading(
+        &self,
+        onyomi: &str,
+        kunyomi: &str,
+    ) -> Result<Vec<Kanji>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["kanji"], rexie::TransactionMode::ReadOnly)?;
+
+        let kanji = transaction.store("kanji")?;
+
+        let onyomi = KeyRange::only(&serde_wasm_bindgen::to_value(onyomi)?)?;
+        let kunyomi = KeyRange::only(&serde_wasm_bindgen::to_value(kunyomi)?)?;
+
+        let onyomi_index = kanji.index("onyomi")?;
+        let kunyomi_index = kanji.index("kunyomi")?;
+
+        let queries = join_all([
+            onyomi_index.get_all(Some(&onyomi), None, None, None),
+            kunyomi_index.get_all(Some(&kunyomi), None, None, None),
+        ])
+        .await;
+
+        let kanji = unique_objects(queries.into_iter().collect::<Result<Vec<_>, _>>()?)?;
+
+        transaction.done().await?;
+
+        Ok(kanji)
+    }
+
+    async fn get_tags(&self, dict_id: u8) -> Result<Vec<Tag>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["tags"], rexie::TransactionMode::ReadOnly)?;
+
+        let tags = transaction.store("tags")?;
+
+        let dict_id = KeyRange::only(&serde_wasm_bindgen::to_value(&dict_id)?)?;
+
+        let tags = tags
+            .index("dict_id")?
+            .get_all(Some(&dict_id), None, None, None)
+            .await?
+            .into_iter()
+            .map(|(_, obj)| serde_wasm_bindgen::from_value(obj))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        transaction.done().await?;
+
+        Ok(tags)
+    }
+
+    async fn get_glossary_matches(
+        &self,
+        tokens: impl IntoIterator<Item = &str>,
+    ) -> Result<Vec<Term>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["terms"], rexie::TransactionMode::ReadOnly)?;
+
+        let terms = transaction.store("terms")?;
+
+        let glossary = terms.index("glossary")?;
+
+        let tokens = tokens
+            .into_iter()
+            .map(|s| -> Result<KeyRange, YomiDictError> {
+                Ok(KeyRange::only(&serde_wasm_bindgen::to_value(s)?)?)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let queries = join_all(
+            tokens
+                .iter()
+                .map(|t| glossary.get_all(Some(t), None, None, None)),
+        )
+        .await;
+
+        let terms = unique_objects(queries.into_iter().collect::<Result<Vec<_>, _>>()?)?;
+
+        transaction.done().await?;
+
+        Ok(terms)
+    }
+
+    async fn get_prefix_matches(
+        &self,
+        prefix: &str,
+        reversed: bool,
+    ) -> Result<Vec<Term>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["terms"], rexie::TransactionMode::ReadOnly)?;
+
+        let terms = transaction.store("terms")?;
+
+        let indices = if reversed {
+            [
+                terms.index("expression_reverse")?,
+                terms.index("reading_reverse")?,
+            ]
+        } else {
+            [terms.index("expression")?, terms.index("reading")?]
+        };
+
+        // Every string starting with the prefix sorts between these bounds
+        let range = KeyRange::bound(
+            &serde_wasm_bindgen::to_value(prefix)?,
+            &serde_wasm_bindgen::to_value(&format!("{prefix}\u{ffff}"))?,
+            false,
+            false,
+        )?;
+
+        let results = join_all(
+            indices
+                .iter()
+                .map(|i| i.get_all(Some(&range), None, None, None)),
+        )
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let terms = unique_objects(results)?;
+
+        transaction.done().await?;
+
+        Ok(terms)
+    }
+
+    async fn get_by_sequence(
+        &self,
+        dict_id: u8,
+        sequence: u32,
+    ) -> Result<Vec<Term>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["terms"], rexie::TransactionMode::ReadOnly)?;
+
+        let terms = transaction.store("terms")?;
+
+        let key = KeyRange::only(&serde_wasm_bindgen::to_value(&(dict_id, sequence))?)?;
+
+        let terms = terms
+            .index("sequence")?
+            .get_all(Some(&key), None, None, None)
+            .await?
+            .into_iter()
+            .map(|(_, obj)| serde_wasm_bindgen::from_value(obj))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        transaction.done().await?;
+
+        Ok(terms)
+    }
+
+    async fn get_term_meta(
+        &self,
+        expressions: impl IntoIterator<Item = &str>,
+    ) -> Result<Vec<TermMeta>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["term_meta"], rexie::TransactionMode::ReadOnly)?;
+
+        let term_meta = transaction.store("term_meta")?;
+
+        let index = term_meta.index("expression")?;
+
+        let expressions = expressions
+            .into_iter()
+            .map(|s| -> Result<KeyRange, YomiDictError> {
+                Ok(KeyRange::only(&serde_wasm_bindgen::to_value(s)?)?)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let queries = join_all(
+            expressions
+                .iter()
+                .map(|e| index.get_all(Some(e), None, None, None)),
+        )
+        .await;
+
+        let term_meta = unique_objects(queries.into_iter().collect::<Result<Vec<_>, _>>()?)?;
+
+        transaction.done().await?;
+
+        Ok(term_meta)
+    }
+
+    async fn get_dictionaries(&self) -> Result<Vec<StoredDict>, YomiDictError> {
+        let transaction = self
+            .rexie
+            .transaction(&["dictionaries"], rexie::TransactionMode::ReadOnly)?;
+
+        let dictionaries = transaction
+            .store("dictionaries")?
+            .get_all(None, None, None, None)
+            .await?
+            .into_iter()
+            .map(|(id, obj)| -> Result<StoredDict, YomiDictError> {
+                let mut index: DictIndex = serde_wasm_bindgen::from_value(obj)?;
+                // The key is stored in the object as well
+                index.extra.remove("id");
+                let has_term_meta = index
+                    .extra
+                    .remove("hasTermMeta")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(true);
+                Ok(StoredDict {
+                    id: serde_wasm_bindgen::from_value(id)?,
+                    index,
+                    has_term_meta,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        transaction.done().await?;
+
+        Ok(dictionaries)
+    }
 }
@@ -3,11 +3,12 @@ use std::collections::HashMap;
 use enumflags2::{bitflags, BitFlags};
 use itertools::Itertools;
 use serde::{Deserialize, Deserializer, Serialize};
-use wana_kana::ConvertJapanese;
+
+use crate::text_variants::{mutate, TextTransformations};
 
 #[bitflags]
 #[repr(u8)]
-#[derive(Deserialize, Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum Rule {
     V1 = 0b0000_0001,   // Verb ichidan
@@ -36,7 +37,21 @@ impl TryFrom<&str> for Rule {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::V1 => "v1",
+            Self::V5 => "v5",
+            Self::Vs => "vs",
+            Self::Vk => "vk",
+            Self::Vz => "vz",
+            Self::AdjI => "adj-i",
+            Self::Iru => "iru",
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Rules(pub BitFlags<Rule>);
 
 impl From<Vec<Rule>> for Rules {
@@ -67,7 +82,7 @@ struct ReasonInfo {
     rules_out: Rules,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 pub struct Reasons(HashMap<String, Vec<ReasonInfo>>);
 
 #[derive(Clone, Debug)]
@@ -75,7 +90,12 @@ pub struct Deinflection {
     pub term: String,
     pub rules: Rules,
     pub source: String,
+    /// Number of chars of the looked up text that `source` stands for.
+    /// Text transformations can make this differ from the length of `source`.
+    pub source_len: usize,
     pub reasons: Vec<String>,
+    /// Whether `source` has kanji of the looked up text replaced by their standard form
+    pub kanji_variant: bool,
 }
 
 impl Deinflection {
@@ -83,8 +103,10 @@ impl Deinflection {
         Self {
             term,
             rules,
+            source_len: source.chars().count(),
             source,
             reasons,
+            kanji_variant: false,
         }
     }
 }
@@ -100,6 +122,12 @@ pub trait Deinflectable {
     fn word_deinflections(&self, reasons: &Reasons) -> Vec<Deinflection>;
     #[must_use]
     fn string_deinflections(&self, reasons: &Reasons) -> Vec<Deinflection>;
+    #[must_use]
+    fn string_deinflections_with(
+        &self,
+        reasons: &Reasons,
+        transformations: &TextTransformations,
+    ) -> Vec<Deinflection>;
 }
 
 impl Deinflectable for &str {
@@ -146,28 +174,43 @@ impl Deinflectable for &str {
 
     /// Get all possible deinflections of the given string and its prefixes given the list of rules.
     fn string_deinflections(&self, reasons: &Reasons) -> Vec<Deinflection> {
-        let substrings: Vec<String> = mutate(self)
+        self.string_deinflections_with(reasons, &TextTransformations::default())
+    }
+
+    /// Like [`Deinflectable::string_deinflections`], looking up only the selected variants of the string
+    fn string_deinflections_with(
+        &self,
+        reasons: &Reasons,
+        transformations: &TextTransformations,
+    ) -> Vec<Deinflection> {
+        let variants = mutate(self, transformations);
+
+        // A prefix found in several variants is attributed to the first one
+        let substrings = variants
             .iter()
-            .flat_map(|s| {
-                (1..=s.chars().count())
+            .flat_map(|v| {
+                (1..=v.source_ends.len())
                     .rev()
-                    .map(|i| &s[..s.chars().take(i).map(char::len_utf8).sum()])
+                    .map(move |i| (v.prefix(i), v.source_len(i), v.kanji_variant))
             })
-            .unique()
-            .map(std::borrow::ToOwned::to_owned)
-            .collect();
+            .unique_by(|(s, _, _)| *s)
+            .collect_vec();
 
         substrings
             .iter()
-            .flat_map(|s| s.as_str().word_deinflections(reasons))
+            .flat_map(|(s, source_len, kanji_variant)| {
+                s.word_deinflections(reasons)
+                    .into_iter()
+                    .map(|d| Deinflection {
+                        source_len: *source_len,
+                        kanji_variant: *kanji_variant,
+                        ..d
+                    })
+            })
             .collect()
     }
 }
 
-fn mutate(s: &str) -> Vec<String> {
-    // TODO Collapse emphatic sequensec
-    vec![s.to_owned(), s.to_hiragana(), s.to_katakana()]
-}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,6 +225,77 @@ mod tests {
         assert!(d.iter().any(|d| d.term.eq("聞く")));
     }
 
+    #[test]
+    fn deinflections_without_transformations() {
+        let reasons = inflection_reasons();
+        let transformations = TextTransformations {
+            kana_conversion: false,
+            romaji_conversion: false,
+            ..Default::default()
+        };
+
+        let d = "kikaremashita".string_deinflections_with(&reasons, &transformations);
+        assert!(!d.iter().any(|d| d.term.eq("きく")));
+
+        let d = "キカレマシタ".string_deinflections_with(
+            &reasons,
+            &TextTransformations {
+                kana_conversion: true,
+                ..transformations
+            },
+        );
+        assert!(d.iter().any(|d| d.term.eq("きく")));
+    }
+
+    #[test]
+    fn deinflections_emphatic() {
+        let reasons = inflection_reasons();
+        let transformations = TextTransformations {
+            collapse_emphatic: true,
+            ..Default::default()
+        };
+
+        let d = "すっっごーーい".string_deinflections_with(&reasons, &transformations);
+
+        let d = d.iter().find(|d| d.term == "すごい").unwrap();
+        assert_eq!(d.source_len, 7);
+    }
+
+    #[test]
+    fn deinflections_half_width() {
+        let reasons = inflection_reasons();
+        let transformations = TextTransformations {
+            half_width_katakana: true,
+            ..Default::default()
+        };
+
+        let d = "ﾀﾍﾞﾏｼﾀ".string_deinflections_with(&reasons, &transformations);
+
+        let d = d.iter().find(|d| d.term == "たべる").unwrap();
+        assert_eq!(d.source, "たべました");
+        assert_eq!(d.source_len, 6);
+    }
+
+    #[test]
+    fn deinflections_kanji_variants() {
+        let reasons = inflection_reasons();
+        let transformations = TextTransformations {
+            kanji_variants: true,
+            ..Default::default()
+        };
+
+        let d = "來た".string_deinflections_with(&reasons, &transformations);
+
+        let d = d.iter().find(|d| d.term == "来る").unwrap();
+        assert_eq!(d.source, "来た");
+        assert_eq!(d.source_len, 2);
+        assert!(d.kanji_variant);
+        assert!(!"来た"
+            .string_deinflections_with(&reasons, &transformations)
+            .iter()
+            .any(|d| d.kanji_variant));
+    }
+
     #[test]
     fn deinflections_romaji() {
         let reasons = inflection_reasons();
@@ -190,6 +304,11 @@ mod tests {
 
         assert!(d.iter().any(|d| d.term.eq("きかれる")));
         assert!(d.iter().any(|d| d.term.eq("きく")));
+
+        let d = "kiitayo".string_deinflections(&reasons);
+        let d = d.iter().find(|d| d.term == "きく").unwrap();
+        assert_eq!(d.source, "きいた");
+        assert_eq!(d.source_len, 5);
     }
 
     struct DeinflectValidTest {
@@ -1,8 +1,9 @@
 use std::{
-    io::{Read, Seek},
-    path::Path,
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
 };
 
+use crate::archive::Archive;
 use crate::kanji_bank::Kanji;
 use crate::terms_bank::Term;
 use crate::YomiDictError;
@@ -10,39 +11,72 @@ use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use crate::{
-    kanji_bank::KanjiTuple,
+    kanji_bank::{KanjiTuple, KanjiTupleV1},
     tag_bank::{Tag, TagTuple},
-    terms_bank::TermTuple,
+    term_meta_bank::{TermMeta, TermMetaTuple},
+    terms_bank::{TermTuple, TermTupleV1},
 };
 
-#[derive(Deserialize_repr, Serialize_repr, Debug)]
+#[derive(Deserialize_repr, Serialize_repr, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Version {
-    // V1 = 1, // We do not support version 1
+    V1 = 1,
     V2 = 2,
     V3 = 3,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum FrequencyMode {
     OccurenceBased,
     RankBased,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct TagMeta {
+    pub category: String,
+    pub order: f32,
+    pub notes: String,
+    pub score: f32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Index {
     pub title: String,
     pub revision: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sequenced: Option<bool>,
     #[serde(alias = "version")]
     pub format: Version,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum_yomitan_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_updatable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub attribution: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub frequency_mode: Option<FrequencyMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_meta: Option<BTreeMap<String, TagMeta>>,
+    /// Fields not known to this version of the crate, kept so the index can be written back out unchanged
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -51,42 +85,56 @@ pub struct Dict {
     pub terms: Vec<Term>,
     pub kanji: Vec<Kanji>,
     pub tags: Vec<Tag>,
+    pub term_meta: Vec<TermMeta>,
 }
 
 impl Dict {
+    /// Read a dictionary from a zip archive.
+    /// With the `tar` feature, `.tar` and `.tar.gz` archives are accepted as well.
+    ///
     /// # Errors
     ///
     /// Will return `Err` if dictionary couldn't be read.
     pub fn new<R: Read + Seek>(reader: R) -> Result<Self, YomiDictError> {
-        let mut archive = zip::ZipArchive::new(reader)?;
+        let mut archive = Archive::new(reader)?;
 
-        let index_json = archive.by_name("index.json")?;
-        let index: Index = serde_json::from_reader(index_json)?;
+        let index = read_index(&mut archive)?;
 
         let mut terms: Vec<Term> = vec![];
         let mut kanji: Vec<Kanji> = vec![];
-        let mut tags: Vec<Tag> = vec![];
+        let mut tags = index_tags(&index);
+        let mut term_meta: Vec<TermMeta> = vec![];
+
+        // Banks are buffered so their strings can be borrowed while parsing
+        let mut buffer = vec![];
 
         for i in 0..archive.len() {
-            let file = archive.by_index(i)?;
+            let (path, mut file) = archive.by_index(i)?;
 
-            match file.enclosed_name() {
-                Some(path) if path == Path::new("index.json") => continue,
+            match path {
+                Some(path) if path == "index.json" => continue,
 
-                Some(path) if path.to_string_lossy().starts_with("term_bank_") => {
-                    let data: Vec<TermTuple> = serde_json::from_reader(file)?;
-                    terms.extend(data.into_iter().map(Term::from));
+                Some(path) if path.starts_with("term_bank_") => {
+                    buffer.clear();
+                    file.read_to_end(&mut buffer)?;
+                    terms.extend(parse_terms(index.format, &buffer)?);
                 }
 
-                Some(path) if path.to_string_lossy().starts_with("kanji_bank_") => {
-                    let data: Vec<KanjiTuple> = serde_json::from_reader(file)?;
-                    kanji.extend(data.into_iter().map(Kanji::from));
+                Some(path) if path.starts_with("kanji_bank_") => {
+                    buffer.clear();
+                    file.read_to_end(&mut buffer)?;
+                    kanji.extend(parse_kanji(index.format, &buffer)?);
                 }
 
-                Some(path) if path.to_string_lossy().starts_with("tag_bank_") => {
+                Some(path) if path.starts_with("tag_bank_") => {
                     let data: Vec<TagTuple> = serde_json::from_reader(file)?;
                     tags.extend(data.into_iter().map(Tag::from));
                 }
+
+                Some(path) if path.starts_with("term_meta_bank_") => {
+                    let data: Vec<TermMetaTuple> = serde_json::from_reader(file)?;
+                    term_meta.extend(data.into_iter().filter_map(TermMetaTuple::into_term_meta));
+                }
                 _ => continue,
             };
         }
@@ -96,6 +144,190 @@ impl Dict {
             terms,
             kanji,
             tags,
+            term_meta,
+        })
+    }
+
+    /// Like [`Dict::new`], but term banks are parsed concurrently on the rayon thread pool.
+    /// Decompression still happens sequentially, and each bank is parsed as soon as it's read.
+    /// Terms are in the same order as with [`Dict::new`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if dictionary couldn't be read.
+    #[cfg(feature = "parallel")]
+    pub fn new_parallel<R: Read + Seek>(reader: R) -> Result<Self, YomiDictError> {
+        use std::sync::mpsc;
+
+        let mut archive = Archive::new(reader)?;
+
+        let index = read_index(&mut archive)?;
+
+        let mut kanji: Vec<Kanji> = vec![];
+        let mut tags = index_tags(&index);
+        let mut term_meta: Vec<TermMeta> = vec![];
+
+        let (sender, receiver) = mpsc::channel();
+
+        rayon::in_place_scope(|scope| -> Result<(), YomiDictError> {
+            let mut bank = 0;
+
+            for i in 0..archive.len() {
+                let (path, mut file) = archive.by_index(i)?;
+
+                match path {
+                    Some(path) if path == "index.json" => continue,
+
+                    Some(path) if path.starts_with("term_bank_") => {
+                        let mut data = vec![];
+                        file.read_to_end(&mut data)?;
+
+                        let (sender, format) = (sender.clone(), index.format);
+                        scope.spawn(move |_| {
+                            // The receiver outlives the scope, so sending can't fail
+                            let _ = sender.send((bank, parse_terms(format, &data)));
+                        });
+                        bank += 1;
+                    }
+
+                    Some(path) if path.starts_with("kanji_bank_") => {
+                        let mut data = vec![];
+                        file.read_to_end(&mut data)?;
+                        kanji.extend(parse_kanji(index.format, &data)?);
+                    }
+
+                    Some(path) if path.starts_with("tag_bank_") => {
+                        let data: Vec<TagTuple> = serde_json::from_reader(file)?;
+                        tags.extend(data.into_iter().map(Tag::from));
+                    }
+
+                    Some(path) if path.starts_with("term_meta_bank_") => {
+                        let data: Vec<TermMetaTuple> = serde_json::from_reader(file)?;
+                        term_meta
+                            .extend(data.into_iter().filter_map(TermMetaTuple::into_term_meta));
+                    }
+                    _ => continue,
+                };
+            }
+
+            Ok(())
+        })?;
+        drop(sender);
+
+        // Banks finish in any order, put them back in archive order
+        let mut term_banks = receiver.into_iter().collect::<Vec<_>>();
+        term_banks.sort_unstable_by_key(|(bank, _)| *bank);
+
+        let terms = term_banks
+            .into_iter()
+            .map(|(_, terms)| terms)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(Self {
+            index,
+            terms,
+            kanji,
+            tags,
+            term_meta,
         })
     }
+
+    /// Write the dictionary as a zip archive in the current (version 3) format.
+    /// Tags from the index' `tagMeta` are written to the tag bank.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the archive couldn't be written.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<(), YomiDictError> {
+        const BANK_SIZE: usize = 10_000;
+
+        let mut archive = zip::ZipWriter::new(writer);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        let index = Index {
+            format: Version::V3,
+            tag_meta: None,
+            ..self.index.clone()
+        };
+        archive.start_file("index.json", options)?;
+        serde_json::to_writer(&mut archive, &index)?;
+
+        for (i, chunk) in self.terms.chunks(BANK_SIZE).enumerate() {
+            archive.start_file(format!("term_bank_{}.json", i + 1), options)?;
+            serde_json::to_writer(
+                &mut archive,
+                &chunk.iter().map(TermTuple::from).collect::<Vec<_>>(),
+            )?;
+        }
+
+        for (i, chunk) in self.kanji.chunks(BANK_SIZE).enumerate() {
+            archive.start_file(format!("kanji_bank_{}.json", i + 1), options)?;
+            serde_json::to_writer(
+                &mut archive,
+                &chunk.iter().map(KanjiTuple::from).collect::<Vec<_>>(),
+            )?;
+        }
+
+        for (i, chunk) in self.tags.chunks(BANK_SIZE).enumerate() {
+            archive.start_file(format!("tag_bank_{}.json", i + 1), options)?;
+            serde_json::to_writer(
+                &mut archive,
+                &chunk.iter().map(TagTuple::from).collect::<Vec<_>>(),
+            )?;
+        }
+
+        for (i, chunk) in self.term_meta.chunks(BANK_SIZE).enumerate() {
+            archive.start_file(format!("term_meta_bank_{}.json", i + 1), options)?;
+            serde_json::to_writer(
+                &mut archive,
+                &chunk.iter().map(TermMetaTuple::from).collect::<Vec<_>>(),
+            )?;
+        }
+
+        archive.finish()?;
+
+        Ok(())
+    }
+}
+
+fn read_index<R: Read + Seek>(archive: &mut Archive<R>) -> Result<Index, YomiDictError> {
+    let index_json = archive.index_json()?;
+    Ok(serde_json::from_reader(index_json)?)
+}
+
+fn index_tags(index: &Index) -> Vec<Tag> {
+    index
+        .tag_meta
+        .iter()
+        .flatten()
+        .map(|(name, meta)| Tag::from_meta(name, meta))
+        .collect()
+}
+
+fn parse_terms(format: Version, bank: &[u8]) -> Result<Vec<Term>, YomiDictError> {
+    let terms = if format == Version::V1 {
+        let data: Vec<TermTupleV1> = serde_json::from_slice(bank)?;
+        data.into_iter().map(Term::from).collect()
+    } else {
+        let data: Vec<TermTuple> = serde_json::from_slice(bank)?;
+        data.into_iter().map(Term::from).collect()
+    };
+
+    Ok(terms)
+}
+
+fn parse_kanji(format: Version, bank: &[u8]) -> Result<Vec<Kanji>, YomiDictError> {
+    let kanji = if format == Version::V1 {
+        let data: Vec<KanjiTupleV1> = serde_json::from_slice(bank)?;
+        data.into_iter().map(Kanji::from).collect()
+    } else {
+        let data: Vec<KanjiTuple> = serde_json::from_slice(bank)?;
+        data.into_iter().map(Kanji::from).collect()
+    };
+
+    Ok(kanji)
 }
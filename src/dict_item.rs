@@ -1,3 +1,5 @@
 pub trait DictItem {
     fn set_dict_id(&mut self, dict_id: u8);
+    /// Fill fields that only exist to be indexed by the database, right before the item is stored
+    fn build_index_keys(&mut self) {}
 }
@@ -0,0 +1,285 @@
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+
+use crate::{
+    kanji_bank::Kanji, tag_bank::Tag, term_meta_bank::TermMeta, terms_bank::Term, Dict,
+    YomiDictError,
+};
+
+/// What identifies a term when comparing or merging dictionaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBy {
+    /// All terms sharing a sequence number form one entry.
+    /// Only for sequenced dictionaries, see [`crate::Index::sequenced`], and only meaningful
+    /// between versions of the same dictionary, as sequence numbers are specific to it.
+    Sequence,
+    /// All terms sharing expression and reading form one entry
+    ExpressionReading,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TermKey {
+    Sequence(u32),
+    ExpressionReading(String, String),
+}
+
+impl KeyBy {
+    /// Terms of unsequenced dictionaries all have sequence 0 and would form a single entry
+    fn check<'a>(self, dicts: impl IntoIterator<Item = &'a Dict>) -> Result<(), YomiDictError> {
+        if self != Self::Sequence {
+            return Ok(());
+        }
+
+        match dicts.into_iter().find(|d| d.index.sequenced != Some(true)) {
+            Some(dict) => Err(YomiDictError::NotSequenced(dict.index.title.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn key(self, term: &Term) -> TermKey {
+        match self {
+            Self::Sequence => TermKey::Sequence(term.sequence),
+            Self::ExpressionReading => {
+                TermKey::ExpressionReading(term.expression.clone(), term.reading.clone())
+            }
+        }
+    }
+}
+
+/// How to treat an entry that exists in more than one of the merged dictionaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Use the entry of the first dictionary containing it
+    KeepFirst,
+    /// Use the entry of the last dictionary containing it
+    KeepLast,
+    /// Use the entries of all dictionaries, dropping exact duplicates
+    KeepAll,
+}
+
+/// Terms of an entry in the old and the new dictionary.
+/// `old` is empty for added entries and `new` is empty for removed ones.
+#[derive(Debug)]
+pub struct TermChange<'a> {
+    pub key: TermKey,
+    pub old: Vec<&'a Term>,
+    pub new: Vec<&'a Term>,
+}
+
+/// A top level `index.json` field that differs, with `None` where the field is absent
+#[derive(Debug, PartialEq)]
+pub struct IndexChange {
+    pub field: String,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub struct DictDiff<'a> {
+    pub index_changes: Vec<IndexChange>,
+    pub added: Vec<TermChange<'a>>,
+    pub removed: Vec<TermChange<'a>>,
+    pub changed: Vec<TermChange<'a>>,
+}
+
+impl DictDiff<'_> {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.index_changes.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+impl Dict {
+    /// Compare this dictionary to a newer version of it
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the terms are keyed by sequence and a dictionary isn't sequenced.
+    pub fn diff<'a>(&'a self, new: &'a Self, key_by: KeyBy) -> Result<DictDiff<'a>, YomiDictError> {
+        key_by.check([self, new])?;
+
+        let old_terms = group_terms(&self.terms, key_by);
+        let new_terms = group_terms(&new.terms, key_by);
+
+        let mut diff = DictDiff {
+            index_changes: index_changes(self, new),
+            added: vec![],
+            removed: vec![],
+            changed: vec![],
+        };
+
+        for (key, old) in &old_terms {
+            match new_terms.get(key) {
+                None => diff.removed.push(TermChange {
+                    key: key.clone(),
+                    old: old.clone(),
+                    new: vec![],
+                }),
+                Some(new) if new != old => diff.changed.push(TermChange {
+                    key: key.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        diff.added = new_terms
+            .into_iter()
+            .filter(|(key, _)| !old_terms.contains_key(key))
+            .map(|(key, new)| TermChange {
+                key,
+                old: vec![],
+                new,
+            })
+            .collect();
+
+        diff.added.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        diff.removed.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        diff.changed.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(diff)
+    }
+
+    /// Combine several dictionaries into one. The index is taken from the first dictionary.
+    /// Terms are matched by `key_by`, kanji by character, tags by name
+    /// and term meta by expression, reading and kind.
+    /// Returns `None` if no dictionaries were given.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the terms are keyed by sequence and a dictionary isn't sequenced.
+    pub fn merge(
+        dicts: impl IntoIterator<Item = Self>,
+        key_by: KeyBy,
+        resolution: ConflictResolution,
+    ) -> Result<Option<Self>, YomiDictError> {
+        let dicts = dicts.into_iter().collect::<Vec<_>>();
+        key_by.check(&dicts)?;
+
+        let mut dicts = dicts.into_iter();
+        let Some(first) = dicts.next() else {
+            return Ok(None);
+        };
+
+        let mut terms = Merger::new(resolution);
+        let mut kanji = Merger::new(resolution);
+        let mut tags = Merger::new(resolution);
+        let mut term_meta = Merger::new(resolution);
+
+        let index = first.index.clone();
+
+        for dict in std::iter::once(first).chain(dicts) {
+            terms.add(dict.terms, |t: &Term| key_by.key(t));
+            kanji.add(dict.kanji, |k: &Kanji| k.character.clone());
+            tags.add(dict.tags, |t: &Tag| t.name.clone());
+            term_meta.add(dict.term_meta, |m: &TermMeta| {
+                (
+                    m.expression.clone(),
+                    m.reading.clone(),
+                    std::mem::discriminant(&m.data),
+                )
+            });
+        }
+
+        Ok(Some(Self {
+            index,
+            terms: terms.finish(),
+            kanji: kanji.finish(),
+            tags: tags.finish(),
+            term_meta: term_meta.finish(),
+        }))
+    }
+}
+
+fn group_terms(terms: &[Term], key_by: KeyBy) -> HashMap<TermKey, Vec<&Term>> {
+    let mut groups: HashMap<TermKey, Vec<&Term>> = HashMap::new();
+    for term in terms {
+        groups.entry(key_by.key(term)).or_default().push(term);
+    }
+    groups
+}
+
+fn index_changes(old: &Dict, new: &Dict) -> Vec<IndexChange> {
+    let as_object = |dict: &Dict| match serde_json::to_value(&dict.index) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+
+    let old = as_object(old);
+    let new = as_object(new);
+
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|field| old.get(*field) != new.get(*field))
+        .map(|field| IndexChange {
+            field: field.clone(),
+            old: old.get(field).cloned(),
+            new: new.get(field).cloned(),
+        })
+        .collect()
+}
+
+/// Collects items of several dictionaries, keeping the order in which keys first appear
+struct Merger<K, T> {
+    resolution: ConflictResolution,
+    order: Vec<K>,
+    items: HashMap<K, Vec<T>>,
+}
+
+impl<K: Eq + Hash + Clone, T: PartialEq> Merger<K, T> {
+    fn new(resolution: ConflictResolution) -> Self {
+        Self {
+            resolution,
+            order: vec![],
+            items: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, items: Vec<T>, key: impl Fn(&T) -> K) {
+        let mut grouped: HashMap<K, Vec<T>> = HashMap::new();
+        let mut order = vec![];
+
+        for item in items {
+            let k = key(&item);
+            if !grouped.contains_key(&k) {
+                order.push(k.clone());
+            }
+            grouped.entry(k).or_default().push(item);
+        }
+
+        for k in order {
+            let new = grouped.remove(&k).unwrap_or_default();
+
+            match self.items.get_mut(&k) {
+                None => {
+                    self.order.push(k.clone());
+                    self.items.insert(k, new);
+                }
+                Some(existing) => match self.resolution {
+                    ConflictResolution::KeepFirst => {}
+                    ConflictResolution::KeepLast => *existing = new,
+                    ConflictResolution::KeepAll => {
+                        for item in new {
+                            if !existing.contains(&item) {
+                                existing.push(item);
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<T> {
+        self.order
+            .iter()
+            .flat_map(|k| self.items.remove(k).unwrap_or_default())
+            .collect()
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::{deinflect::Rule, Dict};
+
+/// Sanity statistics about the contents of a dictionary
+#[derive(Debug, Default)]
+pub struct DictStats {
+    pub term_count: usize,
+    pub kanji_count: usize,
+    pub tag_count: usize,
+    pub term_meta_count: usize,
+    /// Terms with an empty reading, which stands for the expression
+    pub terms_without_reading: usize,
+    /// Every (expression, reading, sequence) combination that occurs more than once, with its count
+    pub duplicate_terms: Vec<((String, String, u32), usize)>,
+    /// Number of terms for each glossary length
+    pub glossary_lengths: BTreeMap<usize, usize>,
+    /// Number of terms that list each rule
+    pub rule_usage: HashMap<Rule, usize>,
+    /// Tags used by terms that aren't defined by a tag bank or the index
+    pub undefined_term_tags: BTreeSet<String>,
+    /// Kanji characters with the tags that aren't defined by a tag bank or the index
+    pub unresolved_kanji_tags: Vec<(String, Vec<String>)>,
+}
+
+impl Dict {
+    /// Collect statistics to check the dictionary's integrity
+    #[must_use]
+    pub fn stats(&self) -> DictStats {
+        let defined_tags: HashSet<&str> = self.tags.iter().map(|t| t.name.as_str()).collect();
+
+        let mut stats = DictStats {
+            term_count: self.terms.len(),
+            kanji_count: self.kanji.len(),
+            tag_count: self.tags.len(),
+            term_meta_count: self.term_meta.len(),
+            ..Default::default()
+        };
+
+        let mut term_keys: HashMap<(&str, &str, u32), usize> = HashMap::new();
+
+        for term in &self.terms {
+            if term.reading_omitted {
+                stats.terms_without_reading += 1;
+            }
+
+            *term_keys
+                .entry((&term.expression, &term.reading, term.sequence))
+                .or_default() += 1;
+
+            *stats
+                .glossary_lengths
+                .entry(term.glossary.len())
+                .or_default() += 1;
+
+            for rule in term.rules.0.iter() {
+                *stats.rule_usage.entry(rule).or_default() += 1;
+            }
+
+            stats.undefined_term_tags.extend(
+                term.definition_tags
+                    .iter()
+                    .chain(&term.term_tags)
+                    .filter(|t| !defined_tags.contains(t.as_str()))
+                    .cloned(),
+            );
+        }
+
+        stats.duplicate_terms = term_keys
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|((expression, reading, sequence), count)| {
+                ((expression.to_owned(), reading.to_owned(), sequence), count)
+            })
+            .collect();
+        stats.duplicate_terms.sort_unstable();
+
+        stats.unresolved_kanji_tags = self
+            .kanji
+            .iter()
+            .filter_map(|kanji| {
+                let unresolved: Vec<String> = kanji
+                    .tags
+                    .iter()
+                    .filter(|t| !defined_tags.contains(t.as_str()))
+                    .cloned()
+                    .collect();
+
+                (!unresolved.is_empty()).then(|| (kanji.character.clone(), unresolved))
+            })
+            .collect();
+
+        stats
+    }
+}
@@ -17,6 +17,16 @@ pub enum YomiDictError {
     JsobjError(serde_wasm_bindgen::Error),
     #[error("Error with storage: `{0}`")]
     StorageError(rexie::Error),
+    #[error("Dictionary `{0}` is not sequenced, its terms can't be keyed by sequence")]
+    NotSequenced(String),
+    #[error("Search pattern `{0}` neither starts nor ends with a literal character")]
+    UnboundedPattern(String),
+}
+
+impl From<std::io::Error> for YomiDictError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
 }
 
 impl From<rexie::Error> for YomiDictError {
@@ -0,0 +1,154 @@
+use itertools::Itertools;
+use wana_kana::utils::{is_char_kana, katakana_to_hiragana};
+
+/// A part of an expression with the reading written above it, if it needs one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuriganaSegment {
+    pub text: String,
+    /// `None` for kana, which is read as written
+    pub reading: Option<String>,
+}
+
+impl FuriganaSegment {
+    fn new(text: impl Into<String>, reading: Option<String>) -> Self {
+        Self {
+            text: text.into(),
+            reading,
+        }
+    }
+}
+
+/// Katakana compare equal to the hiragana of the same sound, converted like the lookup text.
+/// Converting char by char keeps a long vowel mark as it is instead of turning it into a vowel.
+fn to_hiragana(c: char) -> char {
+    katakana_to_hiragana(c.encode_utf8(&mut [0; 4]))
+        .chars()
+        .next()
+        .unwrap_or(c)
+}
+
+/// Split the expression into parts with the part of the reading each stands for,
+/// e.g. `聞く` read as `きく` into `聞` read as `き` and `く`.
+/// Kana of the expression get no reading, even if the reading writes them in the other script.
+/// When the reading can't be matched up with the expression, the whole expression gets the
+/// whole reading.
+#[must_use]
+pub fn distribute_furigana(expression: &str, reading: &str) -> Vec<FuriganaSegment> {
+    let normalized = |s: &str| s.chars().map(to_hiragana).collect::<String>();
+    if reading.is_empty() || normalized(expression) == normalized(reading) {
+        return vec![FuriganaSegment::new(expression, None)];
+    }
+
+    let groups = expression
+        .chars()
+        .group_by(|c| is_char_kana(*c))
+        .into_iter()
+        .map(|(is_kana, chars)| (is_kana, chars.collect::<String>()))
+        .collect_vec();
+    let reading = reading.chars().collect_vec();
+
+    segmentize(&groups, &reading).unwrap_or_else(|| {
+        vec![FuriganaSegment::new(
+            expression,
+            Some(reading.into_iter().collect()),
+        )]
+    })
+}
+
+/// Match the groups of kana and other chars with the reading, one group after the other.
+/// Kana have to appear in the reading as they are, the other groups take the reading in between.
+fn segmentize(groups: &[(bool, String)], reading: &[char]) -> Option<Vec<FuriganaSegment>> {
+    let Some(((is_kana, text), rest)) = groups.split_first() else {
+        return reading.is_empty().then(Vec::new);
+    };
+
+    if *is_kana {
+        let len = text.chars().count();
+        let matches = reading.len() >= len
+            && text
+                .chars()
+                .zip(reading)
+                .all(|(a, b)| to_hiragana(a) == to_hiragana(*b));
+        if !matches {
+            return None;
+        }
+
+        let mut segments = segmentize(rest, &reading[len..])?;
+        segments.insert(0, FuriganaSegment::new(text.as_str(), None));
+        return Some(segments);
+    }
+
+    // Longer readings first, so a following kana group matches as late as possible
+    (1..=reading.len()).rev().find_map(|len| {
+        let mut segments = segmentize(rest, &reading[len..])?;
+        let group_reading = reading[..len].iter().collect();
+        segments.insert(0, FuriganaSegment::new(text.as_str(), Some(group_reading)));
+        Some(segments)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(expression: &str, reading: &str) -> Vec<(String, Option<String>)> {
+        distribute_furigana(expression, reading)
+            .into_iter()
+            .map(|s| (s.text, s.reading))
+            .collect()
+    }
+
+    fn segment(text: &str, reading: Option<&str>) -> (String, Option<String>) {
+        (text.to_owned(), reading.map(str::to_owned))
+    }
+
+    #[test]
+    fn okurigana() {
+        assert_eq!(
+            segments("聞く", "きく"),
+            [segment("聞", Some("き")), segment("く", None)]
+        );
+        assert_eq!(
+            segments("取り扱い", "とりあつかい"),
+            [
+                segment("取", Some("と")),
+                segment("り", None),
+                segment("扱", Some("あつか")),
+                segment("い", None)
+            ]
+        );
+        assert_eq!(
+            segments("お茶", "おちゃ"),
+            [segment("お", None), segment("茶", Some("ちゃ"))]
+        );
+    }
+
+    #[test]
+    fn kana() {
+        assert_eq!(
+            segments("すばやい", "すばやい"),
+            [segment("すばやい", None)]
+        );
+        assert_eq!(segments("コーヒー", ""), [segment("コーヒー", None)]);
+        assert_eq!(
+            segments("ビール瓶", "びーるびん"),
+            [segment("ビール", None), segment("瓶", Some("びん"))]
+        );
+        assert_eq!(
+            segments("ヴァイオリン", "ゔぁいおりん"),
+            [segment("ヴァイオリン", None)]
+        );
+    }
+
+    #[test]
+    fn unmatched() {
+        assert_eq!(
+            segments("今日は", "きょうわ"),
+            [segment("今日は", Some("きょうわ"))]
+        );
+        assert_eq!(
+            segments("大人", "おとな"),
+            [segment("大人", Some("おとな"))]
+        );
+    }
+}
@@ -1,46 +1,354 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
-use serde::{Deserialize, Serialize};
+use itertools::Itertools;
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 
-use crate::dict_item::DictItem;
+use crate::{
+    dict_item::DictItem,
+    tag_bank::{split_tags, tag_list, Tag},
+    terms_bank::BankStr,
+};
 
-#[derive(Deserialize, Debug)]
-pub struct KanjiTuple(
-    String,
-    String,
-    String,
-    String,
-    Vec<String>,
-    HashMap<String, String>,
+/// Kanji row of a version 3 dictionary.
+/// Strings borrow from the bank's buffer unless they contain escapes, so readings and tags
+/// are only allocated once they are split.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct KanjiTuple<'a>(
+    #[serde(borrow)] Cow<'a, str>,
+    #[serde(borrow)] Cow<'a, str>,
+    #[serde(borrow)] Cow<'a, str>,
+    #[serde(borrow)] Cow<'a, str>,
+    Cow<'a, [String]>,
+    Cow<'a, HashMap<String, String>>,
 );
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Kanji row of a version 1 dictionary: the meanings are spread over all trailing elements
+/// and there are no stats.
+#[derive(Debug)]
+pub struct KanjiTupleV1<'a> {
+    character: Cow<'a, str>,
+    onyomi: Cow<'a, str>,
+    kunyomi: Cow<'a, str>,
+    tags: Cow<'a, str>,
+    meanings: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for KanjiTupleV1<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct KanjiTupleV1Visitor;
+
+        impl<'de> Visitor<'de> for KanjiTupleV1Visitor {
+            type Value = KanjiTupleV1<'de>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a version 1 kanji array")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let BankStr(character) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let BankStr(onyomi) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                let BankStr(kunyomi) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+                let BankStr(tags) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(3, &self))?;
+
+                let mut meanings = Vec::new();
+                while let Some(meaning) = seq.next_element()? {
+                    meanings.push(meaning);
+                }
+
+                Ok(KanjiTupleV1 {
+                    character,
+                    onyomi,
+                    kunyomi,
+                    tags,
+                    meanings,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(KanjiTupleV1Visitor)
+    }
+}
+
+/// A kun reading such as `た.べる`, split into the part written with the kanji and the okurigana
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KunReading {
+    pub stem: String,
+    pub okurigana: String,
+    /// Written as `あ-`: the kanji is used as a prefix with this reading
+    pub prefix: bool,
+    /// Written as `-あ`: the kanji is used as a suffix with this reading
+    pub suffix: bool,
+}
+
+impl KunReading {
+    /// The reading without any markers, e.g. `たべる`
+    #[must_use]
+    pub fn full(&self) -> String {
+        format!("{}{}", self.stem, self.okurigana)
+    }
+}
+
+impl From<&str> for KunReading {
+    fn from(reading: &str) -> Self {
+        let suffix = reading.starts_with('-');
+        let prefix = reading.ends_with('-');
+        let reading = reading.trim_matches('-');
+
+        let (stem, okurigana) = reading.split_once('.').unwrap_or((reading, ""));
+
+        Self {
+            stem: stem.to_owned(),
+            okurigana: okurigana.to_owned(),
+            prefix,
+            suffix,
+        }
+    }
+}
+
+impl std::fmt::Display for KunReading {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.suffix {
+            f.write_str("-")?;
+        }
+        f.write_str(&self.stem)?;
+        if !self.okurigana.is_empty() {
+            write!(f, ".{}", self.okurigana)?;
+        }
+        if self.prefix {
+            f.write_str("-")?;
+        }
+        Ok(())
+    }
+}
+
+/// Category of a kanji stat, taken from the tag with the stat's name.
+/// Variants are in the order groups are returned by [`Kanji::grouped_stats`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StatCategory {
+    /// E.g. JLPT level or school grade
+    Class,
+    Frequency,
+    /// E.g. stroke count, or stats without a tag
+    Misc,
+    /// Codes such as SKIP or Unicode
+    Code,
+    /// Indices into printed dictionaries
+    Index,
+    Other(String),
+}
+
+impl From<&str> for StatCategory {
+    fn from(category: &str) -> Self {
+        match category {
+            "class" => Self::Class,
+            "frequency" => Self::Frequency,
+            "misc" | "" => Self::Misc,
+            "code" => Self::Code,
+            "index" => Self::Index,
+            other => Self::Other(other.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KanjiStat {
+    pub name: String,
+    /// Human readable name from the tag's notes, or the stat's name if there is no tag
+    pub notes: String,
+    pub value: String,
+    /// The value, if it is a number
+    pub number: Option<f64>,
+    pub order: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KanjiStatGroup {
+    pub category: StatCategory,
+    pub stats: Vec<KanjiStat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Kanji {
     pub character: String,
-    pub onyomi: String,
-    pub kunyomi: String,
-    pub tags: String,
+    #[serde(deserialize_with = "onyomi_list")]
+    pub onyomi: Vec<String>,
+    #[serde(deserialize_with = "kunyomi_list")]
+    pub kunyomi: Vec<KunReading>,
+    #[serde(with = "tag_list")]
+    pub tags: Vec<String>,
     pub meanings: Vec<String>,
     pub stats: HashMap<String, String>,
     pub dict_id: u8,
+    /// Kun readings with and without okurigana, so the database can index them
+    #[serde(default)]
+    pub(crate) kunyomi_keys: Vec<String>,
+}
+
+fn split_readings(readings: &str) -> impl Iterator<Item = &str> {
+    readings.split(' ').map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn parse_onyomi(readings: &str) -> Vec<String> {
+    split_readings(readings).map(str::to_owned).collect()
+}
+
+fn parse_kunyomi(readings: &str) -> Vec<KunReading> {
+    split_readings(readings).map(KunReading::from).collect()
+}
+
+/// Databases created before readings were parsed store them as space separated strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredReadings<T> {
+    Text(String),
+    List(Vec<T>),
+}
+
+fn onyomi_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match StoredReadings::deserialize(deserializer)? {
+        StoredReadings::Text(readings) => parse_onyomi(&readings),
+        StoredReadings::List(readings) => readings,
+    })
 }
 
-impl From<KanjiTuple> for Kanji {
-    fn from(t: KanjiTuple) -> Self {
+fn kunyomi_list<'de, D>(deserializer: D) -> Result<Vec<KunReading>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match StoredReadings::deserialize(deserializer)? {
+        StoredReadings::Text(readings) => parse_kunyomi(&readings),
+        StoredReadings::List(readings) => readings,
+    })
+}
+
+impl From<KanjiTuple<'_>> for Kanji {
+    fn from(t: KanjiTuple<'_>) -> Self {
         Self {
-            character: t.0,
-            onyomi: t.1,
-            kunyomi: t.2,
-            tags: t.3,
-            meanings: t.4,
-            stats: t.5,
+            character: t.0.into_owned(),
+            onyomi: parse_onyomi(&t.1),
+            kunyomi: parse_kunyomi(&t.2),
+            tags: split_tags(&t.3),
+            meanings: t.4.into_owned(),
+            stats: t.5.into_owned(),
             dict_id: 0,
+            kunyomi_keys: vec![],
         }
     }
 }
 
+impl<'a> From<&'a Kanji> for KanjiTuple<'a> {
+    fn from(k: &'a Kanji) -> Self {
+        Self(
+            Cow::Borrowed(&k.character),
+            Cow::Owned(k.onyomi.join(" ")),
+            Cow::Owned(k.kunyomi.iter().map(ToString::to_string).join(" ")),
+            Cow::Owned(k.tags.join(" ")),
+            Cow::Borrowed(&k.meanings),
+            Cow::Borrowed(&k.stats),
+        )
+    }
+}
+
+impl From<KanjiTupleV1<'_>> for Kanji {
+    fn from(t: KanjiTupleV1<'_>) -> Self {
+        Self {
+            character: t.character.into_owned(),
+            onyomi: parse_onyomi(&t.onyomi),
+            kunyomi: parse_kunyomi(&t.kunyomi),
+            tags: split_tags(&t.tags),
+            meanings: t.meanings,
+            stats: HashMap::new(),
+            dict_id: 0,
+            kunyomi_keys: vec![],
+        }
+    }
+}
+
+impl Kanji {
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Resolve the stats against the tags of the kanji's dictionary and group them by category.
+    /// Tags of other dictionaries are only used if the own dictionary doesn't define the stat.
+    /// Within a group, stats are sorted by tag order and then name.
+    #[must_use]
+    pub fn grouped_stats(&self, tags: &[Tag]) -> Vec<KanjiStatGroup> {
+        self.stats
+            .iter()
+            .map(|(name, value)| {
+                let tag = tags
+                    .iter()
+                    .filter(|t| &t.name == name)
+                    .min_by_key(|t| t.dict_id != self.dict_id);
+
+                let category = tag.map_or(StatCategory::Misc, |t| {
+                    StatCategory::from(t.category.as_str())
+                });
+
+                let stat = KanjiStat {
+                    name: name.clone(),
+                    notes: tag
+                        .map(|t| t.notes.clone())
+                        .filter(|n| !n.is_empty())
+                        .unwrap_or_else(|| name.clone()),
+                    value: value.clone(),
+                    number: value.trim().parse().ok(),
+                    order: tag.map_or(0.0, |t| t.order),
+                };
+
+                (category, stat)
+            })
+            .into_group_map()
+            .into_iter()
+            .sorted_unstable_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(category, stats)| KanjiStatGroup {
+                category,
+                stats: stats
+                    .into_iter()
+                    .sorted_unstable_by(|a, b| {
+                        a.order
+                            .total_cmp(&b.order)
+                            .then_with(|| a.name.cmp(&b.name))
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
 impl DictItem for Kanji {
     fn set_dict_id(&mut self, dict_id: u8) {
         self.dict_id = dict_id;
     }
+
+    fn build_index_keys(&mut self) {
+        self.kunyomi_keys = self
+            .kunyomi
+            .iter()
+            .flat_map(|r| [r.full(), r.stem.clone()])
+            .unique()
+            .collect();
+    }
 }
@@ -0,0 +1,305 @@
+/// Traditional forms (kyūjitai) with their modern form (shinjitai), sorted by the traditional form
+const KYUJITAI: &[(char, char)] = &[
+    ('乘', '乗'),
+    ('亂', '乱'),
+    ('亞', '亜'),
+    ('佛', '仏'),
+    ('來', '来'),
+    ('假', '仮'),
+    ('傳', '伝'),
+    ('僞', '偽'),
+    ('價', '価'),
+    ('儉', '倹'),
+    ('兒', '児'),
+    ('兩', '両'),
+    ('剩', '剰'),
+    ('劍', '剣'),
+    ('劑', '剤'),
+    ('勞', '労'),
+    ('勳', '勲'),
+    ('勵', '励'),
+    ('勸', '勧'),
+    ('區', '区'),
+    ('卷', '巻'),
+    ('參', '参'),
+    ('單', '単'),
+    ('嚴', '厳'),
+    ('囑', '嘱'),
+    ('圈', '圏'),
+    ('國', '国'),
+    ('圍', '囲'),
+    ('圓', '円'),
+    ('圖', '図'),
+    ('團', '団'),
+    ('墮', '堕'),
+    ('壓', '圧'),
+    ('壘', '塁'),
+    ('壞', '壊'),
+    ('壤', '壌'),
+    ('壯', '壮'),
+    ('壹', '壱'),
+    ('壽', '寿'),
+    ('奧', '奥'),
+    ('孃', '嬢'),
+    ('學', '学'),
+    ('寢', '寝'),
+    ('實', '実'),
+    ('寫', '写'),
+    ('寬', '寛'),
+    ('寶', '宝'),
+    ('將', '将'),
+    ('專', '専'),
+    ('對', '対'),
+    ('屬', '属'),
+    ('峽', '峡'),
+    ('嶽', '岳'),
+    ('帶', '帯'),
+    ('廢', '廃'),
+    ('廣', '広'),
+    ('廳', '庁'),
+    ('彈', '弾'),
+    ('徑', '径'),
+    ('從', '従'),
+    ('德', '徳'),
+    ('恆', '恒'),
+    ('惠', '恵'),
+    ('惡', '悪'),
+    ('惱', '悩'),
+    ('愼', '慎'),
+    ('慘', '惨'),
+    ('應', '応'),
+    ('懷', '懐'),
+    ('戀', '恋'),
+    ('戰', '戦'),
+    ('戲', '戯'),
+    ('拂', '払'),
+    ('拔', '抜'),
+    ('拜', '拝'),
+    ('挾', '挟'),
+    ('插', '挿'),
+    ('搖', '揺'),
+    ('搜', '捜'),
+    ('擇', '択'),
+    ('擔', '担'),
+    ('據', '拠'),
+    ('擧', '挙'),
+    ('擴', '拡'),
+    ('收', '収'),
+    ('效', '効'),
+    ('敍', '叙'),
+    ('敕', '勅'),
+    ('數', '数'),
+    ('斷', '断'),
+    ('晝', '昼'),
+    ('曉', '暁'),
+    ('會', '会'),
+    ('條', '条'),
+    ('棧', '桟'),
+    ('榮', '栄'),
+    ('樂', '楽'),
+    ('樓', '楼'),
+    ('樞', '枢'),
+    ('樣', '様'),
+    ('檢', '検'),
+    ('櫻', '桜'),
+    ('權', '権'),
+    ('歐', '欧'),
+    ('歡', '歓'),
+    ('歸', '帰'),
+    ('殘', '残'),
+    ('殼', '殻'),
+    ('毆', '殴'),
+    ('氣', '気'),
+    ('沒', '没'),
+    ('淨', '浄'),
+    ('淺', '浅'),
+    ('渴', '渇'),
+    ('溪', '渓'),
+    ('滯', '滞'),
+    ('滿', '満'),
+    ('潛', '潜'),
+    ('澁', '渋'),
+    ('澤', '沢'),
+    ('濕', '湿'),
+    ('濟', '済'),
+    ('濱', '浜'),
+    ('瀧', '滝'),
+    ('灣', '湾'),
+    ('燈', '灯'),
+    ('燒', '焼'),
+    ('營', '営'),
+    ('爐', '炉'),
+    ('爭', '争'),
+    ('爲', '為'),
+    ('犧', '犠'),
+    ('狹', '狭'),
+    ('獨', '独'),
+    ('獵', '猟'),
+    ('獸', '獣'),
+    ('獻', '献'),
+    ('瓣', '弁'),
+    ('畫', '画'),
+    ('當', '当'),
+    ('疊', '畳'),
+    ('癡', '痴'),
+    ('發', '発'),
+    ('盜', '盗'),
+    ('盡', '尽'),
+    ('眞', '真'),
+    ('碎', '砕'),
+    ('祕', '秘'),
+    ('禪', '禅'),
+    ('禮', '礼'),
+    ('稱', '称'),
+    ('竊', '窃'),
+    ('竝', '並'),
+    ('粹', '粋'),
+    ('絲', '糸'),
+    ('經', '経'),
+    ('綠', '緑'),
+    ('縣', '県'),
+    ('縱', '縦'),
+    ('總', '総'),
+    ('繪', '絵'),
+    ('繼', '継'),
+    ('續', '続'),
+    ('纖', '繊'),
+    ('缺', '欠'),
+    ('聰', '聡'),
+    ('聲', '声'),
+    ('聽', '聴'),
+    ('肅', '粛'),
+    ('腦', '脳'),
+    ('膽', '胆'),
+    ('臟', '臓'),
+    ('臺', '台'),
+    ('與', '与'),
+    ('舊', '旧'),
+    ('舍', '舎'),
+    ('舖', '舗'),
+    ('莊', '荘'),
+    ('莖', '茎'),
+    ('萬', '万'),
+    ('藏', '蔵'),
+    ('藝', '芸'),
+    ('藥', '薬'),
+    ('處', '処'),
+    ('號', '号'),
+    ('螢', '蛍'),
+    ('蟲', '虫'),
+    ('蠶', '蚕'),
+    ('蠻', '蛮'),
+    ('衞', '衛'),
+    ('裝', '装'),
+    ('覺', '覚'),
+    ('覽', '覧'),
+    ('觀', '観'),
+    ('觸', '触'),
+    ('謠', '謡'),
+    ('證', '証'),
+    ('譯', '訳'),
+    ('譽', '誉'),
+    ('讀', '読'),
+    ('變', '変'),
+    ('讓', '譲'),
+    ('豐', '豊'),
+    ('豫', '予'),
+    ('貳', '弐'),
+    ('賣', '売'),
+    ('贊', '賛'),
+    ('踐', '践'),
+    ('輕', '軽'),
+    ('轉', '転'),
+    ('辨', '弁'),
+    ('辭', '辞'),
+    ('辯', '弁'),
+    ('遞', '逓'),
+    ('遲', '遅'),
+    ('邊', '辺'),
+    ('醉', '酔'),
+    ('醫', '医'),
+    ('釀', '醸'),
+    ('釋', '釈'),
+    ('錄', '録'),
+    ('錢', '銭'),
+    ('鎭', '鎮'),
+    ('鐵', '鉄'),
+    ('鑄', '鋳'),
+    ('鑛', '鉱'),
+    ('關', '関'),
+    ('陷', '陥'),
+    ('隨', '随'),
+    ('險', '険'),
+    ('隱', '隠'),
+    ('隸', '隷'),
+    ('雙', '双'),
+    ('雜', '雑'),
+    ('霸', '覇'),
+    ('靈', '霊'),
+    ('靜', '静'),
+    ('顯', '顕'),
+    ('飜', '翻'),
+    ('餘', '余'),
+    ('騷', '騒'),
+    ('驅', '駆'),
+    ('驗', '験'),
+    ('驛', '駅'),
+    ('髓', '髄'),
+    ('體', '体'),
+    ('髮', '髪'),
+    ('鬪', '闘'),
+    ('鷄', '鶏'),
+    ('鹽', '塩'),
+    ('麥', '麦'),
+    ('黑', '黒'),
+    ('默', '黙'),
+    ('點', '点'),
+    ('黨', '党'),
+    ('齊', '斉'),
+    ('齋', '斎'),
+    ('齒', '歯'),
+    ('齡', '齢'),
+    ('龍', '竜'),
+    ('龜', '亀'),
+];
+
+/// Common variant forms (itaiji) of names with the standard form, sorted by the variant
+const ITAIJI: &[(char, char)] = &[
+    ('冨', '富'),
+    ('峯', '峰'),
+    ('嶋', '島'),
+    ('邉', '辺'),
+    ('髙', '高'),
+    ('﨑', '崎'),
+];
+
+/// The standard modern form of a kanji, if the kanji is a known variant
+pub fn standard_form(c: char) -> Option<char> {
+    [KYUJITAI, ITAIJI].into_iter().find_map(|table| {
+        table
+            .binary_search_by_key(&c, |(variant, _)| *variant)
+            .ok()
+            .map(|i| table[i].1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted() {
+        for table in [KYUJITAI, ITAIJI] {
+            assert!(table.windows(2).all(|w| w[0].0 < w[1].0));
+        }
+    }
+
+    #[test]
+    fn standard_forms() {
+        assert_eq!(standard_form('來'), Some('来'));
+        assert_eq!(standard_form('亞'), Some('亜'));
+        assert_eq!(standard_form('灣'), Some('湾'));
+        assert_eq!(standard_form('髙'), Some('高'));
+        assert_eq!(standard_form('来'), None);
+    }
+}
@@ -1,17 +1,40 @@
+mod archive;
 mod db;
 mod db_indexed_db;
 mod deinflect;
 mod dict;
 mod dict_item;
+mod dict_merge;
+mod dict_stats;
 mod error;
+mod furigana;
 mod kanji_bank;
+mod kanji_variants;
+mod search;
+mod sort;
 mod tag_bank;
+mod term_meta_bank;
 mod terms_bank;
+mod text_variants;
 mod translator;
 
 pub use crate::db::DB;
 pub use crate::db_indexed_db::IndexedDB;
-pub use crate::deinflect::{inflection_reasons, Deinflectable, Reasons};
-pub use crate::dict::Dict;
+pub use crate::deinflect::{inflection_reasons, Deinflectable, Reasons, Rule, Rules};
+pub use crate::dict::{Dict, FrequencyMode, Index, TagMeta, Version};
+pub use crate::dict_merge::{
+    ConflictResolution, DictDiff, IndexChange, KeyBy, TermChange, TermKey,
+};
+pub use crate::dict_stats::DictStats;
 pub use crate::error::YomiDictError;
-pub use crate::translator::{DictEntries, DictEntry};
+pub use crate::furigana::{distribute_furigana, FuriganaSegment};
+pub use crate::kanji_bank::{Kanji, KanjiStat, KanjiStatGroup, KunReading, StatCategory};
+pub use crate::search::SearchMode;
+pub use crate::sort::SortCriterion;
+pub use crate::tag_bank::Tag;
+pub use crate::term_meta_bank::{Frequency, PitchAccent, TermMeta, TermMetaData};
+pub use crate::terms_bank::Term;
+pub use crate::text_variants::TextTransformations;
+pub use crate::translator::{
+    DictEntries, DictEntry, FindTermsOptions, ResultMode, Segment, TermFrequency, TermPitch,
+};
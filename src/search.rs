@@ -0,0 +1,263 @@
+use std::cmp::Reverse;
+
+use itertools::Itertools;
+
+use crate::terms_bank::Term;
+
+/// Words too common to narrow down a glossary search. They are not indexed.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "be", "by", "for", "in", "is", "of", "on", "or", "the", "to",
+    "with",
+];
+
+/// Lowercase words of the text, without stop words
+pub fn glossary_tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .filter(|w| !STOP_WORDS.contains(&w.as_str()))
+        .unique()
+        .collect()
+}
+
+/// Sort terms found by a glossary search so the best matches come first.
+/// Terms matching more words of the query are preferred, then terms with an earlier glossary line
+/// equal to the query, then with an earlier line starting with it, then terms with shorter lines.
+pub fn rank_glossary_matches(query: &str, terms: Vec<Term>) -> Vec<Term> {
+    let query_tokens = glossary_tokens(query);
+    let query = query.trim().to_lowercase();
+
+    terms
+        .into_iter()
+        .map(|term| {
+            let matched_tokens = query_tokens
+                .iter()
+                .filter(|t| term.glossary_tokens.contains(t))
+                .count();
+
+            let lines = term.glossary.iter().map(|g| g.trim().to_lowercase());
+            let exact = lines.clone().position(|g| g == query);
+            let prefix = lines.clone().position(|g| g.starts_with(&query));
+            let shortest_line = lines.map(|g| g.chars().count()).min();
+
+            (
+                (
+                    Reverse(matched_tokens),
+                    exact.unwrap_or(usize::MAX),
+                    prefix.unwrap_or(usize::MAX),
+                    shortest_line.unwrap_or(usize::MAX),
+                    term.dict_id,
+                    -term.score,
+                ),
+                term,
+            )
+        })
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, term)| term)
+        .collect()
+}
+
+/// How [`crate::DB::search_terms`] interprets its pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Terms starting with the pattern
+    Prefix,
+    /// Terms ending with the pattern
+    Suffix,
+    /// `*` matches any number of characters and `?` matches exactly one
+    Wildcard,
+}
+
+/// A search pattern for expressions and readings
+pub struct TermPattern {
+    mode: SearchMode,
+    pattern: Vec<char>,
+}
+
+impl TermPattern {
+    pub fn new(pattern: &str, mode: SearchMode) -> Self {
+        Self {
+            mode,
+            pattern: pattern.chars().collect(),
+        }
+    }
+
+    /// The literal part of the pattern that can be looked up with a key range.
+    /// If the bool is true, the key is reversed and has to be looked up in the reversed index.
+    /// `None` if the pattern has no literal start or end, as it could only be matched
+    /// by going through every term.
+    pub fn range_key(&self) -> Option<(String, bool)> {
+        let (key, reversed) = match self.mode {
+            SearchMode::Prefix => (self.pattern.iter().collect(), false),
+            SearchMode::Suffix => (self.pattern.iter().rev().collect(), true),
+            SearchMode::Wildcard => {
+                let prefix: String = self
+                    .pattern
+                    .iter()
+                    .take_while(|c| !is_wildcard(**c))
+                    .collect();
+
+                if prefix.is_empty() {
+                    let reversed_suffix = self
+                        .pattern
+                        .iter()
+                        .rev()
+                        .take_while(|c| !is_wildcard(**c))
+                        .collect();
+                    (reversed_suffix, true)
+                } else {
+                    (prefix, false)
+                }
+            }
+        };
+
+        (!key.is_empty()).then_some((key, reversed))
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+
+        match self.mode {
+            SearchMode::Prefix => text.starts_with(&self.pattern),
+            SearchMode::Suffix => text.ends_with(&self.pattern),
+            SearchMode::Wildcard => wildcard_matches(&self.pattern, &text),
+        }
+    }
+
+    /// Keep terms whose expression or reading matches, shortest and then alphabetically first
+    pub fn filter_matches(&self, terms: Vec<Term>) -> Vec<Term> {
+        terms
+            .into_iter()
+            .filter(|t| self.matches(&t.expression) || self.matches(&t.reading))
+            .sorted_by(|a, b| {
+                (
+                    a.expression.chars().count(),
+                    &a.expression,
+                    &a.reading,
+                    a.dict_id,
+                )
+                    .cmp(&(
+                        b.expression.chars().count(),
+                        &b.expression,
+                        &b.reading,
+                        b.dict_id,
+                    ))
+            })
+            .collect()
+    }
+}
+
+const fn is_wildcard(c: char) -> bool {
+    matches!(c, '*' | '?')
+}
+
+fn wildcard_matches(pattern: &[char], text: &[char]) -> bool {
+    // Greedy matching that backtracks to the most recent `*`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub fn reverse(s: &str) -> String {
+    s.chars().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deinflect::Rules, dict_item::DictItem, terms_bank::Score};
+
+    fn term(expression: &str, glossary: &[&str]) -> Term {
+        let mut term = Term {
+            expression: expression.to_owned(),
+            reading: expression.to_owned(),
+            definition_tags: vec![],
+            rules: Rules::from(vec![]),
+            score: Score(0.0),
+            glossary: glossary.iter().map(|&g| g.to_owned()).collect(),
+            sequence: 0,
+            term_tags: vec![],
+            dict_id: 0,
+            glossary_tokens: vec![],
+            expression_reverse: String::new(),
+            reading_reverse: String::new(),
+            reading_omitted: false,
+        };
+        term.build_index_keys();
+        term
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            glossary_tokens("To hear; to listen (e.g. to music)"),
+            vec!["hear", "listen", "e", "g", "music"]
+        );
+    }
+
+    #[test]
+    fn ranking() {
+        let terms = vec![
+            term("聞こえる", &["to be heard", "to be audible"]),
+            term("聴く", &["to listen (e.g. to music)", "to hear"]),
+            term("聞く", &["to hear"]),
+            term("補聴器", &["hearing aid"]),
+        ];
+
+        let ranked = rank_glossary_matches("to hear", terms);
+
+        assert_eq!(ranked[0].expression, "聞く");
+        assert_eq!(ranked[1].expression, "聴く");
+    }
+
+    #[test]
+    fn wildcards() {
+        let pattern = TermPattern::new("食*る", SearchMode::Wildcard);
+        assert!(pattern.matches("食べる"));
+        assert!(pattern.matches("食る"));
+        assert!(!pattern.matches("食べない"));
+        assert_eq!(pattern.range_key(), Some(("食".to_owned(), false)));
+
+        let pattern = TermPattern::new("*ness", SearchMode::Wildcard);
+        assert!(pattern.matches("kindness"));
+        assert!(!pattern.matches("kindnesses"));
+        assert_eq!(pattern.range_key(), Some(("ssen".to_owned(), true)));
+
+        let pattern = TermPattern::new("?べ*", SearchMode::Wildcard);
+        assert!(pattern.matches("食べる"));
+        assert!(!pattern.matches("べる"));
+        assert_eq!(pattern.range_key(), None);
+
+        assert_eq!(
+            TermPattern::new("*", SearchMode::Wildcard).range_key(),
+            None
+        );
+        assert_eq!(TermPattern::new("", SearchMode::Prefix).range_key(), None);
+    }
+}
@@ -0,0 +1,197 @@
+use std::cmp::{Ordering, Reverse};
+
+use crate::{
+    dict::FrequencyMode,
+    translator::{DictEntries, DictEntry},
+};
+
+/// A key to rank lookup results by. Results are compared by each criterion in turn
+/// until one of them prefers a result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortCriterion {
+    /// Dictionaries added earlier first
+    DictionaryId,
+    /// Dictionaries in the given order first, then the remaining ones as added
+    DictionaryPriority(Vec<u8>),
+    /// Matches of more characters of the text first
+    SourceLength,
+    /// Matches needing fewer deinflection steps first
+    ReasonCount,
+    /// Matches of the expression before matches of the reading
+    PrimaryMatch,
+    /// Higher term scores first
+    Score,
+    /// Terms with more definitions first
+    GlossaryLength,
+    /// More frequent terms according to the frequency dictionary with this id first,
+    /// then terms it has no frequency for
+    Frequency(u8),
+}
+
+impl SortCriterion {
+    /// The order `find_terms` has always used
+    #[must_use]
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::DictionaryId,
+            Self::SourceLength,
+            Self::ReasonCount,
+            Self::PrimaryMatch,
+            Self::Score,
+            Self::GlossaryLength,
+        ]
+    }
+
+    /// `Less` if `a` should come before `b`
+    #[must_use]
+    pub fn compare(&self, a: &DictEntry, b: &DictEntry) -> Ordering {
+        match self {
+            Self::DictionaryId => a.term.dict_id.cmp(&b.term.dict_id),
+            Self::DictionaryPriority(priority) => {
+                let rank = |e: &DictEntry| {
+                    (
+                        priority
+                            .iter()
+                            .position(|d| *d == e.term.dict_id)
+                            .unwrap_or(usize::MAX),
+                        e.term.dict_id,
+                    )
+                };
+                rank(a).cmp(&rank(b))
+            }
+            Self::SourceLength => Reverse(a.source_len).cmp(&Reverse(b.source_len)),
+            Self::ReasonCount => a.reasons.len().cmp(&b.reasons.len()),
+            Self::PrimaryMatch => b.primary_match.cmp(&a.primary_match),
+            Self::Score => b.term.score.cmp(&a.term.score),
+            Self::GlossaryLength => b.term.glossary.len().cmp(&a.term.glossary.len()),
+            // Frequencies belong to results, see `compare_results`
+            Self::Frequency(_) => Ordering::Equal,
+        }
+    }
+
+    /// Like [`SortCriterion::compare`], comparing results by their first definition.
+    /// Frequencies are compared by the most frequent form of each result.
+    #[must_use]
+    pub fn compare_results(&self, a: &DictEntries, b: &DictEntries) -> Ordering {
+        match self {
+            Self::Frequency(dict_id) => {
+                // Lower is more frequent
+                let best = |r: &DictEntries| {
+                    r.frequencies
+                        .iter()
+                        .filter(|f| f.dict_id == *dict_id)
+                        .map(|f| match f.mode {
+                            FrequencyMode::RankBased => f.frequency.value,
+                            FrequencyMode::OccurenceBased => -f.frequency.value,
+                        })
+                        .min_by(f64::total_cmp)
+                };
+
+                match (best(a), best(b)) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+            }
+            _ => self.compare(&a.entries[0], &b.entries[0]),
+        }
+    }
+}
+
+/// Compare definitions of the same result by the first criterion that tells them apart.
+/// How the text was matched ranks results, not their definitions, so only the criteria about
+/// the terms themselves are used. With the defaults, definitions are ordered by dictionary,
+/// score and glossary length.
+pub fn compare_definitions(criteria: &[SortCriterion], a: &DictEntry, b: &DictEntry) -> Ordering {
+    criteria
+        .iter()
+        .filter(|c| {
+            !matches!(
+                c,
+                SortCriterion::SourceLength
+                    | SortCriterion::ReasonCount
+                    | SortCriterion::PrimaryMatch
+            )
+        })
+        .map(|c| c.compare(a, b))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Compare results by the first criterion that tells them apart
+pub fn compare_results(criteria: &[SortCriterion], a: &DictEntries, b: &DictEntries) -> Ordering {
+    criteria
+        .iter()
+        .map(|c| c.compare_results(a, b))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deinflect::Rules, terms_bank::Score, Term};
+
+    fn entry(dict_id: u8, score: f32, source_len: usize) -> DictEntry {
+        DictEntry {
+            term: Term {
+                expression: "聞く".to_owned(),
+                reading: "きく".to_owned(),
+                definition_tags: vec![],
+                rules: Rules::from(vec![]),
+                score: Score(score),
+                glossary: vec![],
+                sequence: 0,
+                term_tags: vec![],
+                dict_id,
+                glossary_tokens: vec![],
+                expression_reverse: String::new(),
+                reading_reverse: String::new(),
+                reading_omitted: false,
+            },
+            reasons: vec![],
+            source_len,
+            source_chars: 0..source_len,
+            source_bytes: 0..source_len * 3,
+            primary_match: true,
+            kanji_variant: false,
+        }
+    }
+
+    #[test]
+    fn criteria() {
+        let (a, b) = (entry(1, 1.0, 2), entry(2, 5.0, 3));
+
+        let defaults = SortCriterion::defaults();
+        assert_eq!(compare_definitions(&defaults, &a, &b), Ordering::Less);
+
+        let by_score = [SortCriterion::Score, SortCriterion::DictionaryId];
+        assert_eq!(compare_definitions(&by_score, &a, &b), Ordering::Greater);
+
+        let by_priority = [SortCriterion::DictionaryPriority(vec![2])];
+        assert_eq!(compare_definitions(&by_priority, &a, &b), Ordering::Greater);
+
+        let by_source = [SortCriterion::SourceLength];
+        assert_eq!(
+            SortCriterion::SourceLength.compare(&a, &b),
+            Ordering::Greater
+        );
+        assert_eq!(compare_definitions(&by_source, &a, &b), Ordering::Equal);
+        assert_eq!(compare_definitions(&[], &a, &b), Ordering::Equal);
+    }
+
+    #[test]
+    fn definitions_ignore_match() {
+        // Same dictionary, the better match has the lower score
+        let (a, b) = (entry(1, 1.0, 3), entry(1, 5.0, 2));
+
+        let defaults = SortCriterion::defaults();
+        assert_eq!(compare_definitions(&defaults, &a, &b), Ordering::Greater);
+
+        let mut c = entry(1, 5.0, 3);
+        c.term.glossary = vec!["to hear".to_owned()];
+        c.primary_match = false;
+        assert_eq!(compare_definitions(&defaults, &b, &c), Ordering::Greater);
+    }
+}
@@ -1,11 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-use crate::dict_item::DictItem;
+use crate::{dict::TagMeta, dict_item::DictItem};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct TagTuple(String, String, f32, String, f32);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tag {
     pub name: String,
     pub category: String,
@@ -28,8 +28,66 @@ impl From<TagTuple> for Tag {
     }
 }
 
+impl From<&Tag> for TagTuple {
+    fn from(t: &Tag) -> Self {
+        Self(
+            t.name.clone(),
+            t.category.clone(),
+            t.order,
+            t.notes.clone(),
+            t.score,
+        )
+    }
+}
+
+impl Tag {
+    /// Create a tag from an entry of the `tagMeta` object in `index.json`
+    #[must_use]
+    pub fn from_meta(name: &str, meta: &TagMeta) -> Self {
+        Self {
+            name: name.to_owned(),
+            category: meta.category.clone(),
+            order: meta.order,
+            notes: meta.notes.clone(),
+            score: meta.score,
+            dict_id: 0,
+        }
+    }
+}
+
 impl DictItem for Tag {
     fn set_dict_id(&mut self, dict_id: u8) {
         self.dict_id = dict_id;
     }
 }
+
+/// Split a space separated tag string as found in term and kanji banks
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(' ')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Stores tag lists as space separated strings, the same way banks do.
+/// This keeps records written before tags were parsed readable.
+pub mod tag_list {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(tags: &[String], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&tags.join(" "))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let tags: Option<String> = Deserialize::deserialize(deserializer)?;
+
+        Ok(tags.as_deref().map(super::split_tags).unwrap_or_default())
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::dict_item::DictItem;
+
+/// Row of a term meta bank: the expression, the kind of data (`freq`, `pitch`, ...) and the data.
+/// The data is interpreted depending on the kind, so it is kept as a JSON value here.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TermMetaTuple(String, String, Value);
+
+/// A frequency as given by a frequency dictionary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frequency {
+    /// Rank or number of occurrences, depending on the dictionary's [`crate::FrequencyMode`]
+    pub value: f64,
+    /// How the dictionary wants the frequency to be shown, e.g. `1234㋕`
+    pub display_value: Option<String>,
+}
+
+/// One pitch accent of a reading
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PitchAccent {
+    /// Mora after which the pitch drops, 0 for none (heiban)
+    pub position: u32,
+    /// Positions of nasal morae, starting at 0
+    pub nasal: Vec<u32>,
+    /// Positions of devoiced morae, starting at 0
+    pub devoice: Vec<u32>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TermMetaData {
+    Frequency(Frequency),
+    /// The pitch accents of the meta's reading
+    Pitch(Vec<PitchAccent>),
+}
+
+/// Data about a term from a meta bank, e.g. its frequency or pitch accents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TermMeta {
+    pub expression: String,
+    /// The reading the data is specific to. `None` applies to all readings of the expression.
+    pub reading: Option<String>,
+    pub data: TermMetaData,
+    pub dict_id: u8,
+}
+
+impl TermMeta {
+    /// Whether the data applies to the expression and reading
+    #[must_use]
+    pub fn applies_to(&self, expression: &str, reading: &str) -> bool {
+        self.expression == expression && self.reading.as_ref().is_none_or(|r| r == reading)
+    }
+}
+
+impl TermMetaTuple {
+    /// Interpret the row. Returns `None` for kinds of data that aren't supported
+    /// and for malformed data.
+    pub fn into_term_meta(self) -> Option<TermMeta> {
+        let Self(expression, mode, data) = self;
+
+        match mode.as_str() {
+            "freq" => {
+                let (reading, frequency) = match data {
+                    Value::Object(mut obj) if obj.contains_key("frequency") => (
+                        obj.remove("reading")
+                            .and_then(|r| r.as_str().map(str::to_owned)),
+                        parse_frequency(obj.remove("frequency")?)?,
+                    ),
+                    data => (None, parse_frequency(data)?),
+                };
+
+                Some(TermMeta {
+                    expression,
+                    reading,
+                    data: TermMetaData::Frequency(frequency),
+                    dict_id: 0,
+                })
+            }
+            "pitch" => {
+                let Value::Object(mut obj) = data else {
+                    return None;
+                };
+
+                let reading = obj.remove("reading")?.as_str()?.to_owned();
+                let pitches = match obj.remove("pitches")? {
+                    Value::Array(pitches) => pitches
+                        .into_iter()
+                        .map(parse_pitch)
+                        .collect::<Option<Vec<_>>>()?,
+                    _ => return None,
+                };
+
+                Some(TermMeta {
+                    expression,
+                    reading: Some(reading),
+                    data: TermMetaData::Pitch(pitches),
+                    dict_id: 0,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The position is either the downstep mora or a pattern of high and low morae, e.g. `LHHL`
+fn parse_pitch(data: Value) -> Option<PitchAccent> {
+    let Value::Object(mut obj) = data else {
+        return None;
+    };
+
+    let position = match obj.remove("position")? {
+        Value::Number(n) => u32::try_from(n.as_u64()?).ok()?,
+        Value::String(pattern) => pattern
+            .find("HL")
+            .map_or(Some(0), |i| u32::try_from(i + 1).ok())?,
+        _ => return None,
+    };
+
+    // Mora lists may be given as a single number
+    let positions = |v: Option<Value>| match v {
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .into_iter()
+            .collect(),
+        Some(Value::Array(a)) => a
+            .iter()
+            .filter_map(|n| n.as_u64().and_then(|n| u32::try_from(n).ok()))
+            .collect(),
+        _ => vec![],
+    };
+
+    Some(PitchAccent {
+        position,
+        nasal: positions(obj.remove("nasal")),
+        devoice: positions(obj.remove("devoice")),
+        tags: obj
+            .remove("tags")
+            .and_then(|t| serde_json::from_value(t).ok())
+            .unwrap_or_default(),
+    })
+}
+
+/// Frequencies are given as a number, a string starting with a number,
+/// or an object with the number and how to display it
+fn parse_frequency(data: Value) -> Option<Frequency> {
+    match data {
+        Value::Number(n) => Some(Frequency {
+            value: n.as_f64()?,
+            display_value: None,
+        }),
+        Value::String(s) => Some(Frequency {
+            value: leading_number(&s)?,
+            display_value: Some(s),
+        }),
+        Value::Object(obj) => Some(Frequency {
+            value: obj.get("value")?.as_f64()?,
+            display_value: obj
+                .get("displayValue")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        }),
+        _ => None,
+    }
+}
+
+fn leading_number(s: &str) -> Option<f64> {
+    let s = s.trim_start();
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    s[..end].parse().ok()
+}
+
+impl From<&TermMeta> for TermMetaTuple {
+    fn from(m: &TermMeta) -> Self {
+        match &m.data {
+            TermMetaData::Frequency(frequency) => {
+                let mut value = json!({ "value": frequency.value });
+                if let Some(display_value) = &frequency.display_value {
+                    value["displayValue"] = json!(display_value);
+                }
+                let data = match &m.reading {
+                    Some(reading) => json!({ "reading": reading, "frequency": value }),
+                    None => value,
+                };
+
+                Self(m.expression.clone(), "freq".to_owned(), data)
+            }
+            TermMetaData::Pitch(pitches) => {
+                let pitches: Vec<Value> = pitches
+                    .iter()
+                    .map(|p| {
+                        let mut pitch = json!({ "position": p.position });
+                        if !p.nasal.is_empty() {
+                            pitch["nasal"] = json!(p.nasal);
+                        }
+                        if !p.devoice.is_empty() {
+                            pitch["devoice"] = json!(p.devoice);
+                        }
+                        if !p.tags.is_empty() {
+                            pitch["tags"] = json!(p.tags);
+                        }
+                        pitch
+                    })
+                    .collect();
+                let data = json!({ "reading": m.reading, "pitches": pitches });
+
+                Self(m.expression.clone(), "pitch".to_owned(), data)
+            }
+        }
+    }
+}
+
+impl DictItem for TermMeta {
+    fn set_dict_id(&mut self, dict_id: u8) {
+        self.dict_id = dict_id;
+    }
+}
@@ -1,27 +1,106 @@
-use std::cmp::Ordering;
+use std::{borrow::Cow, cmp::Ordering};
 
 use enumflags2::BitFlags;
-use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use itertools::Itertools;
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
 use crate::{
     deinflect::{Rule, Rules},
     dict_item::DictItem,
+    search::{glossary_tokens, reverse},
+    tag_bank::{split_tags, tag_list},
 };
 
-#[derive(Deserialize, Debug)]
-pub struct TermTuple(
-    String,
-    String,
-    Option<String>,
-    #[serde(deserialize_with = "from_string")] Rules,
+/// Term row of a version 3 dictionary.
+/// Strings borrow from the bank's buffer unless they contain escapes, so a term's tags and
+/// readings are only allocated once they are split or kept in the `Term`.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TermTuple<'a>(
+    #[serde(borrow)] Cow<'a, str>,
+    #[serde(borrow)] Cow<'a, str>,
+    #[serde(borrow)] Option<BankStr<'a>>,
+    #[serde(deserialize_with = "from_string", serialize_with = "to_string")] Rules,
     f32,
-    Vec<String>,
+    Cow<'a, [String]>,
     u32,
-    String,
+    #[serde(borrow)] Cow<'a, str>,
 );
 
+/// A string of a bank, borrowed from the bank's buffer unless it contains escapes
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct BankStr<'a>(#[serde(borrow)] pub(crate) Cow<'a, str>);
+
+/// Term row of a version 1 dictionary: the glossary is spread over all trailing elements
+/// and there is neither a sequence number nor term tags.
+#[derive(Debug)]
+pub struct TermTupleV1<'a> {
+    expression: Cow<'a, str>,
+    reading: Cow<'a, str>,
+    definition_tags: Option<Cow<'a, str>>,
+    rules: Rules,
+    score: f32,
+    glossary: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for TermTupleV1<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TermTupleV1Visitor;
+
+        impl<'de> Visitor<'de> for TermTupleV1Visitor {
+            type Value = TermTupleV1<'de>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a version 1 term array")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let BankStr(expression) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let BankStr(reading) = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                let definition_tags: Option<BankStr> = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+                let rules: BankRules = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(3, &self))?;
+                let score = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(4, &self))?;
+
+                let mut glossary = Vec::new();
+                while let Some(definition) = seq.next_element()? {
+                    glossary.push(definition);
+                }
+
+                Ok(TermTupleV1 {
+                    expression,
+                    reading,
+                    definition_tags: definition_tags.map(|t| t.0),
+                    rules: rules.0,
+                    score,
+                    glossary,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(TermTupleV1Visitor)
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct Score(f32);
+pub struct Score(pub f32);
 
 impl Ord for Score {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -45,48 +124,181 @@ impl std::ops::Neg for Score {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Term {
     pub expression: String,
     pub reading: String,
-    pub definition_tags: Option<String>, // TODO Make vector
+    #[serde(with = "tag_list")]
+    pub definition_tags: Vec<String>,
     pub rules: Rules,
     pub score: Score,
     pub glossary: Vec<String>,
     pub sequence: u32,
-    pub term_tags: String, // TODO Make vector
+    #[serde(with = "tag_list")]
+    pub term_tags: Vec<String>,
     pub dict_id: u8,
+    /// Words of the glossary, so the database can index them for reverse lookups
+    #[serde(default)]
+    pub(crate) glossary_tokens: Vec<String>,
+    /// Expression and reading written backwards, so the database can look up suffixes
+    #[serde(default)]
+    pub(crate) expression_reverse: String,
+    #[serde(default)]
+    pub(crate) reading_reverse: String,
+    /// The bank gave no reading, so `reading` is a copy of the expression
+    #[serde(default)]
+    pub(crate) reading_omitted: bool,
+}
+
+impl Term {
+    /// Whether the tag is in either the definition or the term tags
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.definition_tags
+            .iter()
+            .chain(&self.term_tags)
+            .any(|t| t == tag)
+    }
+
+    /// Whether the term is marked as popular with the `P` tag
+    #[must_use]
+    pub fn is_popular(&self) -> bool {
+        self.has_tag("P")
+    }
+}
+
+/// Space separated rules as written in term banks, parsed without allocating a string
+struct BankRules(Rules);
+
+impl<'de> Deserialize<'de> for BankRules {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BankRulesVisitor;
+
+        impl<'de> Visitor<'de> for BankRulesVisitor {
+            type Value = BankRules;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a string of space separated rules")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                parse_rules(v).map(BankRules).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(BankRulesVisitor)
+    }
 }
 
 fn from_string<'de, D>(deserializer: D) -> Result<Rules, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s: String = Deserialize::deserialize(deserializer)?;
+    BankRules::deserialize(deserializer).map(|r| r.0)
+}
 
+fn to_string<S>(rules: &Rules, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(
+        &rules
+            .0
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+fn parse_rules(s: &str) -> Result<Rules, String> {
     let mut r = BitFlags::<Rule>::empty();
     r.extend(
         s.split(' ')
             .map(str::trim)
             .filter(|s| !s.is_empty())
-            .map(|s| Rule::try_from(s).map_err(D::Error::custom))
+            .map(Rule::try_from)
             .collect::<Result<Vec<Rule>, _>>()?,
     );
     Ok(Rules(r))
 }
 
-impl From<TermTuple> for Term {
-    fn from(t: TermTuple) -> Self {
+/// The reading as kept in a `Term`, taken from the bank's expression if the bank gave none.
+/// Either way it's allocated once from the bank, like the expression.
+fn bank_reading(expression: &str, reading: Cow<str>) -> String {
+    if reading.is_empty() {
+        expression.to_owned()
+    } else {
+        reading.into_owned()
+    }
+}
+
+impl From<TermTuple<'_>> for Term {
+    fn from(t: TermTuple<'_>) -> Self {
+        let reading_omitted = t.1.is_empty();
+
         Self {
-            reading: if t.1.is_empty() { t.0.clone() } else { t.1 },
-            expression: t.0,
-            definition_tags: t.2,
+            reading: bank_reading(&t.0, t.1),
+            expression: t.0.into_owned(),
+            definition_tags: t.2.map(|tags| split_tags(&tags.0)).unwrap_or_default(),
             rules: t.3,
             score: Score(t.4),
-            glossary: t.5,
+            glossary: t.5.into_owned(),
             sequence: t.6,
-            term_tags: t.7,
+            term_tags: split_tags(&t.7),
             dict_id: 0,
+            glossary_tokens: vec![],
+            expression_reverse: String::new(),
+            reading_reverse: String::new(),
+            reading_omitted,
+        }
+    }
+}
+
+impl<'a> From<&'a Term> for TermTuple<'a> {
+    fn from(t: &'a Term) -> Self {
+        Self(
+            Cow::Borrowed(&t.expression),
+            Cow::Borrowed(if t.reading_omitted { "" } else { &t.reading }),
+            (!t.definition_tags.is_empty())
+                .then(|| BankStr(Cow::Owned(t.definition_tags.join(" ")))),
+            t.rules.clone(),
+            t.score.0,
+            Cow::Borrowed(&t.glossary),
+            t.sequence,
+            Cow::Owned(t.term_tags.join(" ")),
+        )
+    }
+}
+
+impl From<TermTupleV1<'_>> for Term {
+    fn from(t: TermTupleV1<'_>) -> Self {
+        let reading_omitted = t.reading.is_empty();
+
+        Self {
+            reading: bank_reading(&t.expression, t.reading),
+            expression: t.expression.into_owned(),
+            definition_tags: t
+                .definition_tags
+                .as_deref()
+                .map(split_tags)
+                .unwrap_or_default(),
+            rules: t.rules,
+            score: Score(t.score),
+            glossary: t.glossary,
+            sequence: 0,
+            term_tags: vec![],
+            dict_id: 0,
+            glossary_tokens: vec![],
+            expression_reverse: String::new(),
+            reading_reverse: String::new(),
+            reading_omitted,
         }
     }
 }
@@ -95,4 +307,42 @@ impl DictItem for Term {
     fn set_dict_id(&mut self, dict_id: u8) {
         self.dict_id = dict_id;
     }
+
+    fn build_index_keys(&mut self) {
+        self.glossary_tokens = self
+            .glossary
+            .iter()
+            .flat_map(|g| glossary_tokens(g))
+            .unique()
+            .collect();
+        self.expression_reverse = reverse(&self.expression);
+        self.reading_reverse = reverse(&self.reading);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrowed_bank_strings() {
+        // Only the escaped expression has to be unescaped into a new string
+        let bank = r#"[["\u805e\u304f", "", "v5 tag", "v5", 1, ["to hear"], 3, "P"]]"#;
+        let data: Vec<TermTuple> = serde_json::from_slice(bank.as_bytes()).unwrap();
+        let t = &data[0];
+        assert!(matches!(t.0, Cow::Owned(_)));
+        assert!(matches!(t.1, Cow::Borrowed("")));
+        assert!(matches!(t.2, Some(BankStr(Cow::Borrowed("v5 tag")))));
+        assert!(matches!(t.7, Cow::Borrowed("P")));
+
+        let term = Term::from(data.into_iter().next().unwrap());
+        assert_eq!(term.expression, "聞く");
+        assert_eq!(term.reading, "聞く");
+        assert!(term.reading_omitted);
+
+        let bank = br#"[["kiku", "", "", "v5", 1, "to hear", "to listen"]]"#;
+        let data: Vec<TermTupleV1> = serde_json::from_slice(bank).unwrap();
+        assert!(matches!(data[0].expression, Cow::Borrowed("kiku")));
+        assert_eq!(data[0].glossary, ["to hear", "to listen"]);
+    }
 }
@@ -0,0 +1,613 @@
+use itertools::Itertools;
+use wana_kana::{
+    utils::{hiragana_to_katakana, katakana_to_hiragana},
+    ConvertJapanese,
+};
+
+use crate::kanji_variants::standard_form;
+
+/// Variants of the text that are looked up in addition to the text itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextTransformations {
+    /// Convert between hiragana and katakana
+    pub kana_conversion: bool,
+    /// Convert romaji to kana, e.g. `kiku` to `きく`
+    pub romaji_conversion: bool,
+    /// Shorten emphasis such as `すっっごーーい` to `すっごーい` and `すごい`,
+    /// or `かわいいいい` to `かわいい`
+    pub collapse_emphatic: bool,
+    /// Convert half-width katakana to full-width, e.g. `ｶﾞｯｺｳ` to `ガッコウ`
+    pub half_width_katakana: bool,
+    /// Convert full-width letters and digits to half-width and the other way around
+    pub alphanumeric_width: bool,
+    /// Write numbers in kanji numerals, e.g. `3つ` to `三つ`, `１０日` to `十日`
+    /// or `2500円` to `二千五百円`
+    pub numerals: bool,
+    /// Replace traditional and variant kanji with their standard form, e.g. `來る` with `来る`.
+    /// Results found this way are marked, see [`crate::DictEntry::kanji_variant`].
+    pub kanji_variants: bool,
+}
+
+impl Default for TextTransformations {
+    fn default() -> Self {
+        Self {
+            kana_conversion: true,
+            romaji_conversion: true,
+            collapse_emphatic: false,
+            half_width_katakana: false,
+            alphanumeric_width: false,
+            numerals: false,
+            kanji_variants: false,
+        }
+    }
+}
+
+/// A variant of the lookup text that remembers which part of the original text each char covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextVariant {
+    pub text: String,
+    /// For every char of `text`, the number of chars of the original text up to and including it
+    pub source_ends: Vec<usize>,
+    /// Whether kanji of the original text were replaced by their standard form
+    pub kanji_variant: bool,
+}
+
+impl TextVariant {
+    /// The original text, mapping every char to itself
+    pub fn identity(text: String) -> Self {
+        let source_ends = (1..=text.chars().count()).collect();
+        Self {
+            text,
+            source_ends,
+            kanji_variant: false,
+        }
+    }
+
+    /// Number of chars of the original text covered by the first `chars` chars of the variant
+    pub fn source_len(&self, chars: usize) -> usize {
+        chars.checked_sub(1).map_or(0, |i| self.source_ends[i])
+    }
+
+    /// The variant's first `chars` chars
+    pub fn prefix(&self, chars: usize) -> &str {
+        &self.text[..self.text.chars().take(chars).map(char::len_utf8).sum()]
+    }
+
+    /// Convert every char on its own
+    fn map_chars(&self, f: impl Fn(char) -> char) -> Self {
+        Self {
+            text: self.text.chars().map(f).collect(),
+            source_ends: self.source_ends.clone(),
+            kanji_variant: self.kanji_variant,
+        }
+    }
+
+    /// Convert the whole text, e.g. between romaji, hiragana and katakana.
+    /// The text is converted chunk by chunk, each chunk being the fewest chars that convert
+    /// on their own to the next chars of the whole conversion, e.g. `ka` for the `か` of `kana`.
+    /// Within a chunk, a char that only shows up together with the following ones, like the `っ`
+    /// of `kitte`, ends where the rest of the chunk converts to the rest of its conversion.
+    fn convert(&self, f: impl Fn(&str) -> String) -> Self {
+        let text = f(&self.text);
+        let converted = text.chars().collect_vec();
+        let chars = self.text.chars().collect_vec();
+
+        let mut ends = Vec::with_capacity(converted.len());
+        let mut start = 0;
+        while start < chars.len() && ends.len() < converted.len() {
+            let pos = ends.len();
+            let chunk = (1..=CONVERT_CHUNK_LEN.min(chars.len() - start)).find_map(|len| {
+                let chunk_converted = f(&chars[start..start + len].iter().collect::<String>())
+                    .chars()
+                    .collect_vec();
+                converted[pos..]
+                    .starts_with(&chunk_converted)
+                    .then_some((len, chunk_converted))
+            });
+            // Conversions that depend on more of the text take the rest of it as one chunk
+            let (len, chunk_converted) =
+                chunk.unwrap_or((chars.len() - start, converted[pos..].to_vec()));
+
+            let mut chunk_start = 1;
+            for i in 0..chunk_converted.len() {
+                let rest = chunk_converted[i + 1..].iter().collect::<String>();
+                let end = (chunk_start..len)
+                    .find(|&chars_in| {
+                        f(&chars[start + chars_in..start + len]
+                            .iter()
+                            .collect::<String>())
+                            == rest
+                    })
+                    .unwrap_or(len);
+                ends.push(start + end);
+                chunk_start = end.max(1);
+            }
+
+            start += len;
+        }
+        // Chars converted from nothing, which shouldn't happen, cover the whole text
+        ends.resize(converted.len(), chars.len());
+
+        Self {
+            text,
+            source_ends: ends.into_iter().map(|end| self.source_len(end)).collect(),
+            kanji_variant: self.kanji_variant,
+        }
+    }
+}
+
+/// Longest part of the text converted on its own, enough for any romaji syllable like `xtsu`
+/// or a doubled consonant like `tchi`
+const CONVERT_CHUNK_LEN: usize = 6;
+
+/// All variants of the text to look up, starting with the text itself
+pub fn mutate(s: &str, transformations: &TextTransformations) -> Vec<TextVariant> {
+    let mut variants = vec![TextVariant::identity(s.to_owned())];
+
+    // Each normalization is applied on top of the variants before it
+    let mut normalize = |f: fn(&TextVariant) -> Vec<TextVariant>| {
+        let normalized = variants.iter().flat_map(f).collect_vec();
+        variants.extend(normalized);
+        variants = variants.drain(..).unique_by(|v| v.text.clone()).collect();
+    };
+
+    if transformations.half_width_katakana {
+        normalize(|v| vec![convert_half_width_katakana(v)]);
+    }
+    if transformations.alphanumeric_width {
+        normalize(|v| {
+            vec![
+                v.map_chars(to_half_width_alphanumeric),
+                v.map_chars(to_full_width_alphanumeric),
+            ]
+        });
+    }
+    if transformations.numerals {
+        normalize(|v| vec![to_kanji_numerals(v)]);
+    }
+    if transformations.kanji_variants {
+        normalize(|v| {
+            let standard = v.map_chars(|c| standard_form(c).unwrap_or(c));
+            if standard.text == v.text {
+                vec![]
+            } else {
+                vec![TextVariant {
+                    kanji_variant: true,
+                    ..standard
+                }]
+            }
+        });
+    }
+
+    variants = variants
+        .into_iter()
+        .flat_map(|v| {
+            let converted = match (
+                transformations.kana_conversion,
+                transformations.romaji_conversion,
+            ) {
+                (true, true) => vec![
+                    v.convert(|s| s.to_hiragana()),
+                    v.convert(|s| s.to_katakana()),
+                ],
+                (true, false) => vec![
+                    v.convert(katakana_to_hiragana),
+                    v.convert(hiragana_to_katakana),
+                ],
+                (false, true) => vec![v.convert(|s| s.to_kana())],
+                (false, false) => vec![],
+            };
+
+            std::iter::once(v).chain(converted)
+        })
+        .collect();
+
+    if transformations.collapse_emphatic {
+        variants = variants
+            .into_iter()
+            .flat_map(|v| {
+                let collapsed = collapse_emphatic(&v, false);
+                let fully_collapsed = collapse_emphatic(&v, true);
+                [v, collapsed, fully_collapsed]
+            })
+            .collect();
+    }
+
+    variants.into_iter().unique_by(|v| v.text.clone()).collect()
+}
+
+/// Full-width forms of the half-width katakana block, starting at U+FF61
+const HALF_WIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+const HALF_WIDTH_VOICED_MARK: char = 'ﾞ';
+const HALF_WIDTH_SEMI_VOICED_MARK: char = 'ﾟ';
+
+/// Half-width katakana followed by a voiced sound mark become a single voiced katakana
+fn convert_half_width_katakana(variant: &TextVariant) -> TextVariant {
+    let full_width = |c: char| {
+        (c as u32)
+            .checked_sub(0xFF61)
+            .and_then(|i| HALF_WIDTH_KATAKANA.chars().nth(i as usize))
+    };
+
+    let chars = variant.text.chars().collect_vec();
+
+    let mut text = String::new();
+    let mut source_ends = vec![];
+
+    let mut i = 0;
+    while i < chars.len() {
+        let Some(c) = full_width(chars[i]) else {
+            text.push(chars[i]);
+            source_ends.push(variant.source_ends[i]);
+            i += 1;
+            continue;
+        };
+
+        let combined = match chars.get(i + 1) {
+            Some(&HALF_WIDTH_VOICED_MARK) if c == 'ウ' => Some('ヴ'),
+            Some(&HALF_WIDTH_VOICED_MARK)
+                if "カキクケコサシスセソタチツテトハヒフヘホ".contains(c) =>
+            {
+                char::from_u32(c as u32 + 1)
+            }
+            Some(&HALF_WIDTH_SEMI_VOICED_MARK) if "ハヒフヘホ".contains(c) => {
+                char::from_u32(c as u32 + 2)
+            }
+            _ => None,
+        };
+
+        if let Some(combined) = combined {
+            text.push(combined);
+            source_ends.push(variant.source_ends[i + 1]);
+            i += 2;
+        } else {
+            text.push(c);
+            source_ends.push(variant.source_ends[i]);
+            i += 1;
+        }
+    }
+
+    TextVariant {
+        text,
+        source_ends,
+        kanji_variant: variant.kanji_variant,
+    }
+}
+
+/// Full-width forms are offset from ASCII by this much
+const FULL_WIDTH_OFFSET: u32 = 0xFEE0;
+
+fn to_half_width_alphanumeric(c: char) -> char {
+    match c {
+        '０'..='９' | 'Ａ'..='Ｚ' | 'ａ'..='ｚ' => {
+            char::from_u32(c as u32 - FULL_WIDTH_OFFSET).unwrap_or(c)
+        }
+        _ => c,
+    }
+}
+
+fn to_full_width_alphanumeric(c: char) -> char {
+    if c.is_ascii_alphanumeric() {
+        char::from_u32(c as u32 + FULL_WIDTH_OFFSET).unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+const KANJI_DIGITS: [char; 10] = ['〇', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+/// Places within a group of four digits, from the ones up
+const KANJI_PLACES: [Option<char>; 4] = [None, Some('十'), Some('百'), Some('千')];
+/// Groups of four digits, from the ones up
+const KANJI_GROUPS: [Option<char>; 4] = [None, Some('万'), Some('億'), Some('兆')];
+
+/// Write every run of digits as a kanji numeral, e.g. `2500` as `二千五百`.
+/// Each kanji covers the text up to the digit it stands for, and the last one the whole number.
+fn to_kanji_numerals(variant: &TextVariant) -> TextVariant {
+    let digits = variant
+        .text
+        .chars()
+        .map(|c| to_half_width_alphanumeric(c).to_digit(10))
+        .collect_vec();
+
+    let mut text = String::new();
+    let mut source_ends = vec![];
+
+    for (is_number, run) in &digits
+        .iter()
+        .zip(variant.text.chars())
+        .enumerate()
+        .group_by(|(_, (d, _))| d.is_some())
+    {
+        let run = run.collect_vec();
+        if !is_number {
+            for (i, (_, c)) in run {
+                text.push(c);
+                source_ends.push(variant.source_ends[i]);
+            }
+            continue;
+        }
+
+        let start = run[0].0;
+        let run = run
+            .iter()
+            .map(|(_, (d, _))| d.unwrap_or(0) as usize)
+            .collect_vec();
+        let numeral = kanji_numeral(&run);
+        let end = variant.source_ends[start + run.len() - 1];
+        for (n, (c, digit)) in numeral.iter().enumerate() {
+            text.push(*c);
+            source_ends.push(if n + 1 == numeral.len() {
+                end
+            } else {
+                variant.source_ends[start + digit]
+            });
+        }
+    }
+
+    TextVariant {
+        text,
+        source_ends,
+        kanji_variant: variant.kanji_variant,
+    }
+}
+
+/// The kanji of the number, each with the index of the digit it stands for.
+/// Numbers too large for the named groups are written digit by digit.
+fn kanji_numeral(digits: &[usize]) -> Vec<(char, usize)> {
+    let Some(first) = digits.iter().position(|d| *d != 0) else {
+        return vec![(KANJI_DIGITS[0], 0)];
+    };
+    if digits.len() - first > KANJI_PLACES.len() * KANJI_GROUPS.len() {
+        return digits
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (KANJI_DIGITS[*d], i))
+            .collect();
+    }
+
+    let mut numeral = vec![];
+    for (i, d) in digits.iter().enumerate().skip(first) {
+        let place = digits.len() - 1 - i;
+        let (group, place) = (place / KANJI_PLACES.len(), place % KANJI_PLACES.len());
+
+        if *d != 0 {
+            // Ten, hundred and thousand are written without one
+            if *d != 1 || place == 0 {
+                numeral.push((KANJI_DIGITS[*d], i));
+            }
+            if let Some(c) = KANJI_PLACES[place] {
+                numeral.push((c, i));
+            }
+        }
+
+        let group_start = i.saturating_sub(KANJI_PLACES.len() - 1 - place).max(first);
+        let group_used = digits[group_start..=i].iter().any(|d| *d != 0);
+        if let (0, Some(c), true) = (place, KANJI_GROUPS[group], group_used) {
+            numeral.push((c, i));
+        }
+    }
+
+    numeral
+}
+
+const fn is_emphatic(c: char) -> bool {
+    matches!(c, 'っ' | 'ッ' | 'ー')
+}
+
+const fn is_vowel(c: char) -> bool {
+    matches!(
+        c,
+        'あ' | 'い'
+            | 'う'
+            | 'え'
+            | 'お'
+            | 'ぁ'
+            | 'ぃ'
+            | 'ぅ'
+            | 'ぇ'
+            | 'ぉ'
+            | 'ア'
+            | 'イ'
+            | 'ウ'
+            | 'エ'
+            | 'オ'
+            | 'ァ'
+            | 'ィ'
+            | 'ゥ'
+            | 'ェ'
+            | 'ォ'
+    )
+}
+
+/// Shorten runs of small tsu and long vowel marks to one and runs of three or more vowels to two.
+/// A full collapse removes the marks entirely and leaves a single vowel.
+/// Runs at the start of the text are kept, as they can't be emphasis.
+/// The kept chars of a run cover the whole run in the original text.
+fn collapse_emphatic(variant: &TextVariant, full: bool) -> TextVariant {
+    let chars = variant.text.chars().collect_vec();
+
+    let mut text = String::new();
+    let mut source_ends = vec![];
+
+    let mut start = 0;
+    while start < chars.len() {
+        let c = chars[start];
+        let end = chars[start..]
+            .iter()
+            .position(|d| *d != c)
+            .map_or(chars.len(), |len| start + len);
+        let run = end - start;
+
+        let keep = if start == 0 {
+            run
+        } else if is_emphatic(c) {
+            usize::from(!full)
+        } else if is_vowel(c) && full {
+            1
+        } else if is_vowel(c) {
+            run.min(2)
+        } else {
+            run
+        };
+
+        for i in end - keep..end {
+            text.push(c);
+            source_ends.push(variant.source_ends[i]);
+        }
+
+        start = end;
+    }
+
+    TextVariant {
+        text,
+        source_ends,
+        kanji_variant: variant.kanji_variant,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapse() {
+        let original = TextVariant::identity("すっっごーーい".to_owned());
+
+        let collapsed = collapse_emphatic(&original, false);
+        assert_eq!(collapsed.text, "すっごーい");
+        assert_eq!(collapsed.source_len(3), 4);
+        assert_eq!(collapsed.source_len(5), 7);
+
+        let collapsed = collapse_emphatic(&original, true);
+        assert_eq!(collapsed.text, "すごい");
+        assert_eq!(collapsed.source_len(2), 4);
+
+        let original = TextVariant::identity("かわいいいい".to_owned());
+        assert_eq!(collapse_emphatic(&original, false).text, "かわいい");
+        assert_eq!(collapse_emphatic(&original, true).text, "かわい");
+
+        let original = TextVariant::identity("ーーい".to_owned());
+        assert_eq!(collapse_emphatic(&original, true).text, "ーーい");
+    }
+
+    #[test]
+    fn half_width_katakana() {
+        assert_eq!(HALF_WIDTH_KATAKANA.chars().count(), 0xFF9F - 0xFF61 + 1);
+
+        let converted = convert_half_width_katakana(&TextVariant::identity("ｶﾞｯｺｳﾊﾟﾝ".to_owned()));
+        assert_eq!(converted.text, "ガッコウパン");
+        assert_eq!(converted.source_len(1), 2);
+        assert_eq!(converted.source_len(6), 8);
+    }
+
+    #[test]
+    fn widths_and_numerals() {
+        let transformations = TextTransformations {
+            alphanumeric_width: true,
+            numerals: true,
+            ..Default::default()
+        };
+
+        let variants = mutate("ＣＤ３枚", &transformations);
+        assert!(variants.iter().any(|v| v.text == "CD3枚"));
+        assert!(variants.iter().any(|v| v.text == "ＣＤ三枚"));
+
+        let variants = mutate("3つ", &transformations);
+        assert!(variants.iter().any(|v| v.text == "３つ"));
+        assert!(variants.iter().any(|v| v.text == "三つ"));
+        assert!(variants.iter().all(|v| v.source_ends == vec![1, 2]));
+
+        let variants = mutate("１０日", &transformations);
+        let numeral = variants.iter().find(|v| v.text == "十日").unwrap();
+        assert_eq!(numeral.source_ends, vec![2, 3]);
+    }
+
+    #[test]
+    fn numerals() {
+        let numeral = |s: &str| to_kanji_numerals(&TextVariant::identity(s.to_owned())).text;
+
+        assert_eq!(numeral("0"), "〇");
+        assert_eq!(numeral("23"), "二十三");
+        assert_eq!(numeral("105"), "百五");
+        assert_eq!(numeral("2500円"), "二千五百円");
+        assert_eq!(numeral("1000"), "千");
+        assert_eq!(numeral("12000"), "一万二千");
+        assert_eq!(numeral("100000000"), "一億");
+        assert_eq!(numeral("300010005"), "三億一万五");
+        assert_eq!(numeral("3時05分"), "三時五分");
+        assert_eq!(
+            numeral("12345678901234567"),
+            "一二三四五六七八九〇一二三四五六七"
+        );
+
+        let variant = to_kanji_numerals(&TextVariant::identity("23日".to_owned()));
+        assert_eq!(variant.source_ends, vec![1, 1, 2, 3]);
+    }
+
+    #[test]
+    fn kanji_variants() {
+        let transformations = TextTransformations {
+            kanji_variants: true,
+            ..Default::default()
+        };
+
+        let variants = mutate("學校に來た", &transformations);
+
+        assert!(!variants[0].kanji_variant);
+        let standard = variants.iter().find(|v| v.text == "学校に来た").unwrap();
+        assert!(standard.kanji_variant);
+
+        let variants = mutate("学校", &transformations);
+        assert!(variants.iter().all(|v| !v.kanji_variant));
+
+        let variants = mutate("學校", &TextTransformations::default());
+        assert_eq!(variants.len(), 1);
+    }
+
+    #[test]
+    fn romaji_source_ends() {
+        let transformations = TextTransformations {
+            romaji_conversion: true,
+            ..Default::default()
+        };
+
+        let variants = mutate("kanna", &transformations);
+        let kana = variants.iter().find(|v| v.text == "かんな").unwrap();
+        assert_eq!(kana.source_ends, vec![2, 3, 5]);
+
+        let variants = mutate("kitte", &transformations);
+        let kana = variants.iter().find(|v| v.text == "きって").unwrap();
+        assert_eq!(kana.source_len(2), 3);
+        assert_eq!(kana.source_len(3), 5);
+
+        let variants = mutate("nyanko", &transformations);
+        let kana = variants.iter().find(|v| v.text == "にゃんこ").unwrap();
+        assert_eq!(kana.source_ends, vec![3, 3, 4, 6]);
+
+        let variants = mutate("カタカナ", &transformations);
+        let kana = variants.iter().find(|v| v.text == "かたかな").unwrap();
+        assert_eq!(kana.source_ends, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn convert_long_text() {
+        let text = "kikimashita".repeat(200);
+        let variant = TextVariant::identity(text.clone()).convert(|s| s.to_hiragana());
+
+        assert_eq!(variant.text, "ききました".repeat(200));
+        assert_eq!(variant.source_len(5), 11);
+        assert_eq!(variant.source_len(1000), text.len());
+    }
+
+    #[test]
+    fn variants() {
+        let transformations = TextTransformations {
+            collapse_emphatic: true,
+            ..Default::default()
+        };
+
+        let variants = mutate("スッゴイ", &transformations);
+        assert_eq!(variants[0].text, "スッゴイ");
+        assert!(variants.iter().any(|v| v.text == "すごい"));
+    }
+}
@@ -1,23 +1,138 @@
 #![allow(clippy::future_not_send)]
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
+use futures::future::join_all;
 use itertools::Itertools;
 
-use crate::{db::DBImpl, deinflect::Reasons, terms_bank::Term, Deinflectable, YomiDictError};
+use crate::{
+    db::{DBImpl, StoredDict},
+    deinflect::Reasons,
+    dict::FrequencyMode,
+    furigana::{distribute_furigana, FuriganaSegment},
+    sort::{compare_definitions, compare_results, SortCriterion},
+    term_meta_bank::{Frequency, PitchAccent, TermMetaData},
+    terms_bank::{Score, Term},
+    text_variants::TextTransformations,
+    Deinflectable, YomiDictError,
+};
 
 #[derive(Debug)]
 pub struct DictEntry {
     pub term: Term,
     pub reasons: Vec<String>,
+    /// Number of chars of the looked up text the term was found for
     pub source_len: usize,
+    /// The chars of the looked up text the term was found for.
+    /// Text transformations are undone, so this is a range of the text as given.
+    pub source_chars: Range<usize>,
+    /// The same part of the looked up text as `source_chars`, in bytes
+    pub source_bytes: Range<usize>,
     pub primary_match: bool,
+    /// Whether the term was only found after replacing kanji of the text
+    /// by their standard form, e.g. `來` by `来`
+    pub kanji_variant: bool,
 }
 
 #[derive(Debug)]
 pub struct DictEntries {
     pub expression: String,
     pub reading: String,
+    /// Every expression and reading pair of the group.
+    /// Only merged lookups give more than the group's own expression and reading.
+    pub forms: Vec<(String, String)>,
+    /// The expression split into parts with their part of the reading, for ruby text
+    pub furigana: Vec<FuriganaSegment>,
     pub entries: Vec<DictEntry>,
+    /// Frequencies of the forms from frequency dictionaries
+    pub frequencies: Vec<TermFrequency>,
+    /// Pitch accents of the forms from pitch accent dictionaries
+    pub pitches: Vec<TermPitch>,
+}
+
+/// The pitch accents of one of the forms of a result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermPitch {
+    pub dict_id: u8,
+    pub expression: String,
+    pub reading: String,
+    pub pitches: Vec<PitchAccent>,
+}
+
+/// A frequency of one of the forms of a result
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermFrequency {
+    pub dict_id: u8,
+    pub expression: String,
+    pub reading: String,
+    pub frequency: Frequency,
+    /// Whether lower or higher values are more frequent
+    pub mode: FrequencyMode,
+}
+
+/// A part of a text split into words by [`crate::DB::parse_text`]
+#[derive(Debug)]
+pub struct Segment {
+    pub text: String,
+    /// The chars of the parsed text the segment covers
+    pub source_chars: Range<usize>,
+    /// The bytes of the parsed text the segment covers
+    pub source_bytes: Range<usize>,
+    /// The best results for the segment, empty if no term was found for it.
+    /// The source ranges of the entries are ranges of the parsed text.
+    pub results: Vec<DictEntries>,
+}
+
+/// How the terms found by a lookup are combined into results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultMode {
+    /// One result per expression and reading, as [`crate::DB::find_terms`]
+    #[default]
+    Group,
+    /// One result per term, i.e. per definition of a dictionary
+    Split,
+    /// One result per sequence of the main dictionary, as [`crate::DB::find_terms_merged`].
+    /// Same as [`ResultMode::Group`] if the main dictionary isn't sequenced.
+    Merge { main_dict: u8 },
+}
+
+/// Options for [`crate::DB::find_terms_with_options`]
+#[derive(Debug, Clone)]
+pub struct FindTermsOptions {
+    pub mode: ResultMode,
+    /// Return at most this many results
+    pub max_results: Option<usize>,
+    /// Only return terms of these dictionaries
+    pub dictionaries: Option<Vec<u8>>,
+    /// Look up deinflected forms of the text; otherwise only the text and its prefixes
+    pub deinflect: bool,
+    pub transformations: TextTransformations,
+    /// Ignore matches of fewer characters of the text
+    pub min_source_len: usize,
+    /// How to rank results and the definitions within a result. Definitions are only
+    /// ranked by the criteria about the terms, not by how the text was matched.
+    pub sort_by: Vec<SortCriterion>,
+}
+
+impl Default for FindTermsOptions {
+    fn default() -> Self {
+        Self {
+            mode: ResultMode::default(),
+            max_results: None,
+            dictionaries: None,
+            deinflect: true,
+            transformations: TextTransformations::default(),
+            min_source_len: 1,
+            sort_by: SortCriterion::defaults(),
+        }
+    }
+}
+
+impl FindTermsOptions {
+    fn includes_dict(&self, dict_id: u8) -> bool {
+        self.dictionaries
+            .as_ref()
+            .is_none_or(|dicts| dicts.contains(&dict_id))
+    }
 }
 
 /// Returns a list of terms that could be derived by deinflecting the input text or its substrings.
@@ -25,9 +140,21 @@ pub struct DictEntries {
 pub async fn get_raw_terms(
     text: &str,
     reasons: &Reasons,
+    options: &FindTermsOptions,
     db: &impl DBImpl,
 ) -> Result<Vec<DictEntry>, YomiDictError> {
-    let text_deinflections = text.string_deinflections(reasons);
+    let no_reasons = Reasons::default();
+    let reasons = if options.deinflect {
+        reasons
+    } else {
+        &no_reasons
+    };
+
+    let text_deinflections = text
+        .string_deinflections_with(reasons, &options.transformations)
+        .into_iter()
+        .filter(|d| d.source_len >= options.min_source_len)
+        .collect_vec();
 
     let lookup_strings = text_deinflections.iter().map(|d| d.term.as_str());
 
@@ -41,7 +168,7 @@ pub async fn get_raw_terms(
                 v.into_iter()
                     .sorted_unstable_by_key(|d| {
                         (
-                            std::cmp::Reverse(d.source.chars().count()),
+                            std::cmp::Reverse(d.source_len),
                             std::cmp::Reverse(d.reasons.len()),
                         )
                     })
@@ -54,6 +181,7 @@ pub async fn get_raw_terms(
         .get_raw_matches(lookup_strings)
         .await?
         .into_iter()
+        .filter(|term| options.includes_dict(term.dict_id))
         .filter_map(|term| {
             let derivations = if term_derivations.contains_key(&term.expression) {
                 Some((&term_derivations[&term.expression], true))
@@ -73,8 +201,11 @@ pub async fn get_raw_terms(
             derivation.map(|(d, primary_match)| DictEntry {
                 term,
                 reasons: d.reasons.clone(),
-                source_len: d.source.chars().count(),
+                source_len: d.source_len,
+                source_chars: 0..d.source_len,
+                source_bytes: 0..text.chars().take(d.source_len).map(char::len_utf8).sum(),
                 primary_match,
+                kanji_variant: d.kanji_variant,
             })
         })
         .collect();
@@ -82,48 +213,400 @@ pub async fn get_raw_terms(
     Ok(terms)
 }
 
+/// Returns a list of terms that could be derived by deinflecting the input text or its substrings,
+/// combined into results according to the options.
+/// `dictionaries` are the dictionaries of the database, fetched once by the caller.
+pub async fn get_terms(
+    text: &str,
+    reasons: &Reasons,
+    options: &FindTermsOptions,
+    dictionaries: &[StoredDict],
+    db: &impl DBImpl,
+) -> Result<Vec<DictEntries>, YomiDictError> {
+    let mut terms = match options.mode {
+        ResultMode::Group => get_grouped_terms(text, reasons, options, dictionaries, db).await,
+        ResultMode::Split => get_split_terms(text, reasons, options, dictionaries, db).await,
+        ResultMode::Merge { main_dict } => {
+            get_merged_terms(text, reasons, main_dict, options, dictionaries, db).await
+        }
+    }?;
+
+    if let Some(max_results) = options.max_results {
+        terms.truncate(max_results);
+    }
+
+    Ok(terms)
+}
+
+/// Longest text looked up at once while parsing. This is a deliberate limit that keeps the
+/// lookup at each position cheap, terms longer than this are split into several segments.
+const PARSE_LOOKUP_LEN: usize = 24;
+
+/// Splits the text into words from left to right. At each position the results of the longest
+/// match are taken, and the position moves past the match.
+/// Chars where no term is found become segments of their own without results.
+/// Matches are at most [`PARSE_LOOKUP_LEN`] chars long.
+pub async fn parse_text(
+    text: &str,
+    reasons: &Reasons,
+    options: &FindTermsOptions,
+    db: &impl DBImpl,
+) -> Result<Vec<Segment>, YomiDictError> {
+    let lookup_options = FindTermsOptions {
+        max_results: None,
+        ..options.clone()
+    };
+    let dictionaries = db.get_dictionaries().await?;
+
+    let mut segments = vec![];
+    let (mut chars, mut bytes) = (0, 0);
+    while bytes < text.len() {
+        let rest = &text[bytes..];
+        let lookup_text = &rest[..rest
+            .char_indices()
+            .nth(PARSE_LOOKUP_LEN)
+            .map_or(rest.len(), |(i, _)| i)];
+
+        // Results merged from several forms may match more of the text than their first entry
+        let result_len = |r: &DictEntries| r.entries.iter().map(|e| e.source_len).max();
+
+        let mut results =
+            get_terms(lookup_text, reasons, &lookup_options, &dictionaries, db).await?;
+        let len = results.iter().filter_map(result_len).max().unwrap_or(1);
+        results.retain(|r| result_len(r) == Some(len));
+        if let Some(max_results) = options.max_results {
+            results.truncate(max_results);
+        }
+
+        let byte_len: usize = rest.chars().take(len).map(char::len_utf8).sum();
+        for entry in results.iter_mut().flat_map(|r| &mut r.entries) {
+            entry.source_chars = entry.source_chars.start + chars..entry.source_chars.end + chars;
+            entry.source_bytes = entry.source_bytes.start + bytes..entry.source_bytes.end + bytes;
+        }
+
+        segments.push(Segment {
+            text: rest[..byte_len].to_owned(),
+            source_chars: chars..chars + len,
+            source_bytes: bytes..bytes + byte_len,
+            results,
+        });
+        chars += len;
+        bytes += byte_len;
+    }
+
+    Ok(segments)
+}
+
+/// Returns a list of terms that could be derived by deinflecting the input text or its substrings.
+/// Every term is its own result.
+pub async fn get_split_terms(
+    text: &str,
+    reasons: &Reasons,
+    options: &FindTermsOptions,
+    dictionaries: &[StoredDict],
+    db: &impl DBImpl,
+) -> Result<Vec<DictEntries>, YomiDictError> {
+    let entries = get_raw_terms(text, reasons, options, db).await?;
+
+    let terms = entries
+        .into_iter()
+        .map(|entry| DictEntries {
+            expression: entry.term.expression.clone(),
+            reading: entry.term.reading.clone(),
+            forms: vec![(entry.term.expression.clone(), entry.term.reading.clone())],
+            furigana: distribute_furigana(&entry.term.expression, &entry.term.reading),
+            entries: vec![entry],
+            frequencies: vec![],
+            pitches: vec![],
+        })
+        .collect();
+
+    finish_groups(terms, options, dictionaries, db).await
+}
+
 /// Returns a list of terms that could be derived by deinflecting the input text or its substrings.
 /// The list is processed to be grouped. Groups share an identical expression and reading.
 pub async fn get_grouped_terms(
     text: &str,
     reasons: &Reasons,
+    options: &FindTermsOptions,
+    dictionaries: &[StoredDict],
     db: &impl DBImpl,
 ) -> Result<Vec<DictEntries>, YomiDictError> {
-    let entries = get_raw_terms(text, reasons, db).await?;
+    let entries = get_raw_terms(text, reasons, options, db).await?;
 
-    let terms = entries
+    finish_groups(
+        group_by_form(entries, &options.sort_by),
+        options,
+        dictionaries,
+        db,
+    )
+    .await
+}
+
+/// Returns a list of terms that could be derived by deinflecting the input text or its substrings.
+/// Terms of the main dictionary sharing a sequence number are merged into one group, together
+/// with all other terms of that sequence and the definitions of other dictionaries for any of
+/// its forms. Terms of other dictionaries that aren't part of such a group are grouped
+/// by expression and reading as in [`get_grouped_terms`].
+/// If the main dictionary isn't sequenced, all its terms share a meaningless sequence number,
+/// so the results are grouped as in [`get_grouped_terms`] instead.
+pub async fn get_merged_terms(
+    text: &str,
+    reasons: &Reasons,
+    main_dict: u8,
+    options: &FindTermsOptions,
+    dictionaries: &[StoredDict],
+    db: &impl DBImpl,
+) -> Result<Vec<DictEntries>, YomiDictError> {
+    let sequenced = dictionaries
+        .iter()
+        .any(|d| d.id == main_dict && d.index.sequenced == Some(true));
+    if !sequenced {
+        return get_grouped_terms(text, reasons, options, dictionaries, db).await;
+    }
+
+    let entries = get_raw_terms(text, reasons, options, db).await?;
+
+    let (main, mut secondary): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|e| e.term.dict_id == main_dict);
+
+    let sequences = main
+        .into_iter()
+        .into_group_map_by(|e| e.term.sequence)
+        .into_iter()
+        .collect_vec();
+
+    let sequence_terms = join_all(
+        sequences
+            .iter()
+            .map(|(sequence, _)| db.get_by_sequence(main_dict, *sequence)),
+    )
+    .await;
+
+    let mut groups = vec![];
+    for ((_, mut entries), terms) in sequences.into_iter().zip(sequence_terms) {
+        entries.sort_unstable_by_key(match_key);
+        let best = &entries[0];
+        let (expression, reading) = (best.term.expression.clone(), best.term.reading.clone());
+        let (reasons, source_len, source_chars, source_bytes, primary_match, kanji_variant) = (
+            best.reasons.clone(),
+            best.source_len,
+            best.source_chars.clone(),
+            best.source_bytes.clone(),
+            best.primary_match,
+            best.kanji_variant,
+        );
+
+        let terms = terms?;
+        let forms = terms
+            .iter()
+            .map(|t| (t.expression.clone(), t.reading.clone()))
+            .unique()
+            .collect_vec();
+
+        // Senses of the entry that weren't matched themselves count as found by the best match
+        for term in terms {
+            if !entries.iter().any(|e| e.term == term) {
+                entries.push(DictEntry {
+                    term,
+                    reasons: reasons.clone(),
+                    source_len,
+                    source_chars: source_chars.clone(),
+                    source_bytes: source_bytes.clone(),
+                    primary_match,
+                    kanji_variant,
+                });
+            }
+        }
+
+        groups.push(DictEntries {
+            furigana: distribute_furigana(&expression, &reading),
+            expression,
+            reading,
+            forms,
+            entries,
+            frequencies: vec![],
+            pitches: vec![],
+        });
+    }
+
+    // Other dictionaries may only define some of the forms, which weren't necessarily matched
+    let form_expressions = groups
+        .iter()
+        .flat_map(|g| g.forms.iter().map(|(expression, _)| expression.as_str()))
+        .unique()
+        .collect_vec();
+    let secondary_terms = if form_expressions.is_empty() {
+        vec![]
+    } else {
+        db.get_raw_matches(form_expressions).await?
+    };
+
+    for group in &mut groups {
+        let is_form = |t: &Term| {
+            t.dict_id != main_dict
+                && group.forms.iter().any(|(expression, reading)| {
+                    *expression == t.expression && *reading == t.reading
+                })
+        };
+
+        let (matched, rest): (Vec<_>, Vec<_>) =
+            secondary.into_iter().partition(|e| is_form(&e.term));
+        secondary = rest;
+
+        let best = &group.entries[0];
+        let unmatched = secondary_terms
+            .iter()
+            .filter(|t| {
+                is_form(t)
+                    && options.includes_dict(t.dict_id)
+                    && !matched.iter().any(|e| e.term == **t)
+            })
+            .map(|t| DictEntry {
+                term: t.clone(),
+                reasons: best.reasons.clone(),
+                source_len: best.source_len,
+                source_chars: best.source_chars.clone(),
+                source_bytes: best.source_bytes.clone(),
+                primary_match: best.primary_match,
+                kanji_variant: best.kanji_variant,
+            })
+            .collect_vec();
+
+        group.entries.extend(matched);
+        group.entries.extend(unmatched);
+        group
+            .entries
+            .sort_unstable_by(|a, b| compare_definitions(&options.sort_by, a, b));
+    }
+
+    groups.extend(group_by_form(secondary, &options.sort_by));
+
+    finish_groups(groups, options, dictionaries, db).await
+}
+
+/// Groups entries sharing an identical expression and reading
+fn group_by_form(entries: Vec<DictEntry>, criteria: &[SortCriterion]) -> Vec<DictEntries> {
+    entries
         .into_iter()
         .into_group_map_by(|t| (t.term.expression.clone(), t.term.reading.clone()))
         .into_iter()
         .map(|((expression, reading), entries)| {
             // Sort definitions in same word
             DictEntries {
+                forms: vec![(expression.clone(), reading.clone())],
+                furigana: distribute_furigana(&expression, &reading),
                 expression,
                 reading,
                 entries: entries
                     .into_iter()
-                    .sorted_unstable_by_key(|e| {
-                        (
-                            e.term.dict_id,
-                            -e.term.score,
-                            std::cmp::Reverse(e.term.glossary.len()),
-                        )
-                    })
+                    .sorted_unstable_by(|a, b| compare_definitions(criteria, a, b))
                     .collect::<Vec<_>>(),
+                frequencies: vec![],
+                pitches: vec![],
             }
         })
-        .sorted_unstable_by_key(|e| {
-            // Sort words
-            (
-                e.entries[0].term.dict_id,
-                std::cmp::Reverse(e.entries[0].source_len),
-                e.entries[0].reasons.len(),
-                !e.entries[0].primary_match,
-                -e.entries[0].term.score,
-                std::cmp::Reverse(e.entries[0].term.glossary.len()),
-            )
-        })
+        .collect()
+}
+
+/// Order of matches by how well they fit the text, best first
+fn match_key(e: &DictEntry) -> (std::cmp::Reverse<usize>, usize, bool, Score) {
+    (
+        std::cmp::Reverse(e.source_len),
+        e.reasons.len(),
+        !e.primary_match,
+        -e.term.score,
+    )
+}
+
+/// Attach meta data of the forms and sort the results
+async fn finish_groups(
+    mut groups: Vec<DictEntries>,
+    options: &FindTermsOptions,
+    dictionaries: &[StoredDict],
+    db: &impl DBImpl,
+) -> Result<Vec<DictEntries>, YomiDictError> {
+    add_term_meta(&mut groups, options, dictionaries, db).await?;
+
+    Ok(sort_groups(groups, &options.sort_by))
+}
+
+async fn add_term_meta(
+    groups: &mut [DictEntries],
+    options: &FindTermsOptions,
+    dictionaries: &[StoredDict],
+    db: &impl DBImpl,
+) -> Result<(), YomiDictError> {
+    let meta_dicts = dictionaries
+        .iter()
+        .filter(|d| d.has_term_meta && options.includes_dict(d.id))
         .collect_vec();
+    if meta_dicts.is_empty() {
+        return Ok(());
+    }
 
-    Ok(terms)
+    let expressions = groups
+        .iter()
+        .flat_map(|g| g.forms.iter().map(|(expression, _)| expression.as_str()))
+        .unique()
+        .collect_vec();
+    if expressions.is_empty() {
+        return Ok(());
+    }
+
+    let term_meta = db
+        .get_term_meta(expressions)
+        .await?
+        .into_iter()
+        .filter(|m| options.includes_dict(m.dict_id))
+        .collect_vec();
+    if term_meta.is_empty() {
+        return Ok(());
+    }
+
+    // Frequency dictionaries that don't declare their mode are assumed to count occurrences
+    let modes: HashMap<u8, FrequencyMode> = meta_dicts
+        .iter()
+        .filter_map(|d| d.index.frequency_mode.map(|mode| (d.id, mode)))
+        .collect();
+
+    for group in groups {
+        for (expression, reading) in &group.forms {
+            for meta in term_meta
+                .iter()
+                .filter(|m| m.applies_to(expression, reading))
+            {
+                match &meta.data {
+                    TermMetaData::Frequency(frequency) => group.frequencies.push(TermFrequency {
+                        dict_id: meta.dict_id,
+                        expression: expression.clone(),
+                        reading: reading.clone(),
+                        frequency: frequency.clone(),
+                        mode: modes
+                            .get(&meta.dict_id)
+                            .copied()
+                            .unwrap_or(FrequencyMode::OccurenceBased),
+                    }),
+                    TermMetaData::Pitch(pitches) => group.pitches.push(TermPitch {
+                        dict_id: meta.dict_id,
+                        expression: expression.clone(),
+                        reading: reading.clone(),
+                        pitches: pitches.clone(),
+                    }),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sort words by their best definition
+fn sort_groups(groups: Vec<DictEntries>, criteria: &[SortCriterion]) -> Vec<DictEntries> {
+    groups
+        .into_iter()
+        .sorted_unstable_by(|a, b| compare_results(criteria, a, b))
+        .collect_vec()
 }
@@ -1,6 +1,7 @@
 use std::io::Cursor;
 
-use rexie::Rexie;
+use rexie::{ObjectStore, Rexie, TransactionMode};
+use serde::Serialize;
 use wasm_bindgen_test::wasm_bindgen_test;
 use yomi_dict::{Dict, IndexedDB, DB};
 
@@ -28,3 +29,94 @@ async fn test_db_steps() {
     }
     assert_eq!(sum, steps.total_count);
 }
+
+#[wasm_bindgen_test]
+async fn test_find_kanji_by_reading() {
+    cleanup_db("test_find_kanji_by_reading").await;
+
+    let file = include_bytes!("dict_v1.zip");
+
+    let dict = Dict::new(Cursor::new(file)).unwrap();
+
+    let db = IndexedDB::new("test_find_kanji_by_reading").await.unwrap();
+
+    db.add_dict(dict).await.unwrap();
+
+    let by_onyomi = db.find_kanji_by_reading("ぶん").await.unwrap();
+    assert!(by_onyomi.iter().any(|k| k.character == "聞"));
+
+    let by_kunyomi = db.find_kanji_by_reading("きこえる").await.unwrap();
+    assert!(by_kunyomi.iter().any(|k| k.character == "聞"));
+
+    let by_stem = db.find_kanji_by_reading("き").await.unwrap();
+    assert_eq!(by_stem.len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn test_migrate_kanji_readings() {
+    cleanup_db("test_migrate_kanji_readings").await;
+
+    // A version 1 database, which stored readings as strings
+    let old = Rexie::builder("test_migrate_kanji_readings")
+        .version(1)
+        .add_object_store(
+            ObjectStore::new("kanji")
+                .key_path("id")
+                .auto_increment(true),
+        )
+        .build()
+        .await
+        .unwrap();
+    let transaction = old
+        .transaction(&["kanji"], TransactionMode::ReadWrite)
+        .unwrap();
+    let row = serde_json::json!({
+        "character": "聞",
+        "onyomi": "ブン モン",
+        "kunyomi": "き.く き.こえる",
+        "tags": "",
+        "meanings": ["hear"],
+        "stats": {},
+        "dict_id": 1,
+    })
+    .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+    .unwrap();
+    transaction
+        .store("kanji")
+        .unwrap()
+        .add(&row, None)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    old.close();
+
+    let db = IndexedDB::new("test_migrate_kanji_readings").await.unwrap();
+
+    let kanji = db.find_kanji_by_reading("きこえる").await.unwrap();
+    assert_eq!(kanji.len(), 1);
+    assert_eq!(kanji[0].onyomi, ["ブン", "モン"]);
+    assert_eq!(db.find_kanji_by_reading("もん").await.unwrap().len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn test_find_by_sequence() {
+    cleanup_db("test_find_by_sequence").await;
+
+    let file = include_bytes!("dict.zip");
+
+    let dict = Dict::new(Cursor::new(file)).unwrap();
+    let sequence = dict.terms[0].sequence;
+    let expected = dict.terms.iter().filter(|t| t.sequence == sequence).count();
+
+    let db = IndexedDB::new("test_find_by_sequence").await.unwrap();
+
+    db.add_dict(dict).await.unwrap();
+
+    let terms = db.find_by_sequence(1, sequence).await.unwrap();
+    assert_eq!(terms.len(), expected);
+    assert!(terms
+        .iter()
+        .all(|t| t.sequence == sequence && t.dict_id == 1));
+
+    assert!(db.find_by_sequence(2, sequence).await.unwrap().is_empty());
+}
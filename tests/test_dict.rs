@@ -1,6 +1,9 @@
 use std::io::Cursor;
 
-use yomi_dict::Dict;
+use yomi_dict::{
+    ConflictResolution, Dict, Frequency, FrequencyMode, Kanji, KeyBy, KunReading, PitchAccent,
+    Rule, StatCategory, Tag, TermKey, TermMetaData, YomiDictError,
+};
 
 #[test]
 fn test_read_dict() {
@@ -14,3 +17,462 @@ fn test_read_dict() {
     assert!(!d.tags.is_empty() && d.tags[0].name == "name");
     assert!(!d.kanji.is_empty() && d.kanji[0].character == "character");
 }
+
+#[test]
+fn test_read_modern_index() {
+    let file = include_bytes!("dict_modern.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert_eq!(d.index.title, "modernDict");
+    assert_eq!(d.index.is_updatable, Some(true));
+    assert_eq!(d.index.source_language.as_deref(), Some("ja"));
+    assert_eq!(d.index.target_language.as_deref(), Some("en"));
+    assert_eq!(d.index.minimum_yomitan_version.as_deref(), Some("24.1.1.0"));
+    assert!(d
+        .tags
+        .iter()
+        .any(|t| t.name == "meta_tag" && t.category == "partOfSpeech"));
+
+    // Unknown fields survive a round trip
+    let json = serde_json::to_value(&d.index).unwrap();
+    assert_eq!(json["someFutureField"]["nested"][2], 3);
+    assert_eq!(json["downloadUrl"], "https://example.com/dict.zip");
+
+    // Only fields of the input are written back
+    let input: serde_json::Value =
+        serde_json::from_str(include_str!("dict_modern/index.json")).unwrap();
+    let keys = |v: &serde_json::Value| v.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+    assert_eq!(keys(&json).len(), keys(&input).len());
+    assert!(keys(&input).iter().all(|k| json.get(k).is_some()));
+}
+
+#[test]
+fn test_write_index_without_nulls() {
+    let file = include_bytes!("dict.zip");
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    let mut written = Cursor::new(vec![]);
+    d.write(&mut written).unwrap();
+    written.set_position(0);
+
+    let mut archive = zip::ZipArchive::new(written).unwrap();
+    let index: serde_json::Value =
+        serde_json::from_reader(archive.by_name("index.json").unwrap()).unwrap();
+
+    assert!(index.as_object().unwrap().values().all(|v| !v.is_null()));
+    assert_eq!(index["title"], "testDict");
+}
+
+#[test]
+fn test_read_v1_dict() {
+    let file = include_bytes!("dict_v1.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert_eq!(d.index.title, "v1Dict");
+    assert_eq!(d.terms.len(), 2);
+    assert_eq!(d.terms[0].expression, "聞く");
+    assert_eq!(d.terms[0].glossary, vec!["to hear", "to listen"]);
+    assert_eq!(d.terms[1].reading, "no_reading");
+    assert_eq!(d.kanji[0].meanings, vec!["hear", "ask", "listen"]);
+    assert!(d.kanji[0].stats.is_empty());
+    assert!(d
+        .tags
+        .iter()
+        .any(|t| t.name == "v5" && t.notes == "Godan verb"));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_read_dict_parallel() {
+    let file = include_bytes!("dict.zip");
+
+    let sequential = Dict::new(Cursor::new(file)).unwrap();
+    let parallel = Dict::new_parallel(Cursor::new(file)).unwrap();
+
+    assert_eq!(sequential.terms.len(), parallel.terms.len());
+    assert!(sequential
+        .terms
+        .iter()
+        .zip(&parallel.terms)
+        .all(|(a, b)| a.expression == b.expression && a.sequence == b.sequence));
+    assert_eq!(sequential.kanji.len(), parallel.kanji.len());
+    assert_eq!(sequential.tags.len(), parallel.tags.len());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_read_dict_parallel_bank_order() {
+    // The archive has term_bank_10.json before term_bank_2.json
+    let file = include_bytes!("dict_banks.zip");
+
+    let sequential = Dict::new(Cursor::new(file)).unwrap();
+    let parallel = Dict::new_parallel(Cursor::new(file)).unwrap();
+
+    assert_eq!(sequential.terms, parallel.terms);
+    let expressions = parallel
+        .terms
+        .iter()
+        .map(|t| t.expression.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(expressions, ["bank 1", "bank 10", "bank 2"]);
+}
+
+#[cfg(feature = "bzip2")]
+#[test]
+fn test_read_dict_bzip2() {
+    let file = include_bytes!("dict_bzip2.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert_eq!(d.index.title, "testDict");
+    assert!(!d.terms.is_empty() && d.terms[0].expression == "some text");
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn test_read_dict_tar_gz() {
+    let file = include_bytes!("dict.tar.gz");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert_eq!(d.index.title, "testDict");
+    assert!(!d.terms.is_empty() && d.terms[0].expression == "some text");
+    assert!(!d.kanji.is_empty() && d.kanji[0].character == "character");
+}
+
+#[cfg(not(feature = "tar"))]
+#[test]
+fn test_read_dict_tar_gz_unsupported() {
+    let file = include_bytes!("dict.tar.gz");
+
+    assert!(matches!(
+        Dict::new(Cursor::new(file)),
+        Err(yomi_dict::YomiDictError::UnsupportedArchive(_))
+    ));
+}
+
+#[test]
+fn test_dict_stats() {
+    let file = include_bytes!("dict.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+    let stats = d.stats();
+
+    assert_eq!(stats.term_count, d.terms.len());
+    assert_eq!(stats.kanji_count, 1);
+    assert_eq!(stats.tag_count, 1);
+    assert_eq!(stats.terms_without_reading, 1); // no_reading
+    assert!(stats.duplicate_terms.is_empty());
+    assert_eq!(stats.glossary_lengths[&0], 1);
+    assert_eq!(stats.rule_usage[&Rule::V5], 2);
+    assert_eq!(stats.rule_usage[&Rule::AdjI], 4);
+    assert!(stats.undefined_term_tags.contains("definition_tag1"));
+    assert!(stats.undefined_term_tags.contains("term_tag_2"));
+    assert_eq!(
+        stats.unresolved_kanji_tags,
+        vec![(
+            "character".to_owned(),
+            vec!["tag1".to_owned(), "tag2".to_owned()]
+        )]
+    );
+}
+
+#[test]
+fn test_dict_stats_explicit_reading() {
+    let file = include_bytes!("dict_modern.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    // An explicit reading that equals the expression isn't missing
+    assert_eq!(d.terms[1].reading, d.terms[1].expression);
+    assert_eq!(d.stats().terms_without_reading, 1); // modern text
+}
+
+#[test]
+fn test_write_dict() {
+    let file = include_bytes!("dict.zip");
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    let mut written = Cursor::new(vec![]);
+    d.write(&mut written).unwrap();
+    written.set_position(0);
+
+    let reread = Dict::new(written).unwrap();
+
+    assert_eq!(d.index, reread.index);
+    assert_eq!(d.terms, reread.terms);
+    assert_eq!(d.kanji, reread.kanji);
+    assert_eq!(d.tags, reread.tags);
+}
+
+#[test]
+fn test_write_term_meta() {
+    let file = include_bytes!("dict_pitch.zip");
+    let mut d = Dict::new(Cursor::new(file)).unwrap();
+    d.term_meta.extend(
+        Dict::new(Cursor::new(include_bytes!("dict_freq.zip")))
+            .unwrap()
+            .term_meta,
+    );
+
+    let mut written = Cursor::new(vec![]);
+    d.write(&mut written).unwrap();
+    written.set_position(0);
+
+    let reread = Dict::new(written).unwrap();
+
+    assert_eq!(d.term_meta, reread.term_meta);
+}
+
+#[test]
+fn test_diff_dict() {
+    let file = include_bytes!("dict.zip");
+    let old = Dict::new(Cursor::new(file)).unwrap();
+    let mut new = Dict::new(Cursor::new(file)).unwrap();
+
+    assert!(old.diff(&new, KeyBy::Sequence).unwrap().is_empty());
+
+    new.index.revision = "test2".to_owned();
+    new.terms.retain(|t| t.expression != "聞く");
+    new.terms[0].glossary.push("Def 3".to_owned());
+    let mut added = new.terms[0].clone();
+    added.sequence = 100;
+    new.terms.push(added);
+
+    let diff = old.diff(&new, KeyBy::Sequence).unwrap();
+
+    assert_eq!(diff.index_changes.len(), 1);
+    assert_eq!(diff.index_changes[0].field, "revision");
+    assert_eq!(diff.removed.len(), 1);
+    assert_eq!(diff.removed[0].key, TermKey::Sequence(3));
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].key, TermKey::Sequence(1));
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.added[0].key, TermKey::Sequence(100));
+}
+
+#[test]
+fn test_merge_dicts() {
+    let file = include_bytes!("dict.zip");
+    let first = Dict::new(Cursor::new(file)).unwrap();
+    let mut second = Dict::new(Cursor::new(file)).unwrap();
+    second.terms[0].glossary = vec!["Changed".to_owned()];
+    let term_count = first.terms.len();
+
+    let merged = Dict::merge(
+        [first, second],
+        KeyBy::ExpressionReading,
+        ConflictResolution::KeepLast,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(merged.terms.len(), term_count);
+    assert_eq!(merged.terms[0].glossary, vec!["Changed"]);
+    assert_eq!(merged.tags.len(), 1);
+
+    let first = Dict::new(Cursor::new(file)).unwrap();
+    let mut second = Dict::new(Cursor::new(file)).unwrap();
+    second.terms[0].glossary = vec!["Changed".to_owned()];
+
+    let merged = Dict::merge(
+        [first, second],
+        KeyBy::ExpressionReading,
+        ConflictResolution::KeepAll,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(merged.terms.len(), term_count + 1);
+    assert_eq!(merged.terms[1].glossary, vec!["Changed"]);
+}
+
+#[test]
+fn test_key_unsequenced_by_sequence() {
+    let sequenced = Dict::new(Cursor::new(include_bytes!("dict.zip"))).unwrap();
+    let unsequenced = Dict::new(Cursor::new(include_bytes!("dict_modern.zip"))).unwrap();
+
+    assert!(matches!(
+        sequenced.diff(&unsequenced, KeyBy::Sequence),
+        Err(YomiDictError::NotSequenced(title)) if title == "modernDict"
+    ));
+    assert!(sequenced
+        .diff(&unsequenced, KeyBy::ExpressionReading)
+        .is_ok());
+
+    assert!(matches!(
+        Dict::merge(
+            [sequenced, unsequenced],
+            KeyBy::Sequence,
+            ConflictResolution::KeepFirst
+        ),
+        Err(YomiDictError::NotSequenced(_))
+    ));
+}
+
+#[test]
+fn test_read_kanji_with_string_readings() {
+    // Kanji as stored by databases from before readings were parsed
+    let kanji: Kanji = serde_json::from_value(serde_json::json!({
+        "character": "聞",
+        "onyomi": "ブン モン",
+        "kunyomi": "き.く",
+        "tags": "",
+        "meanings": ["hear"],
+        "stats": {},
+        "dict_id": 1,
+    }))
+    .unwrap();
+
+    assert_eq!(kanji.onyomi, ["ブン", "モン"]);
+    assert_eq!(kanji.kunyomi[0].stem, "き");
+    assert_eq!(kanji.kunyomi[0].okurigana, "く");
+}
+
+#[test]
+fn test_parse_tags() {
+    let file = include_bytes!("dict.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert!(d.terms[0].definition_tags.is_empty());
+    assert_eq!(d.terms[0].term_tags, vec!["tag1"]);
+    assert_eq!(
+        d.terms[1].definition_tags,
+        vec!["definition_tag1", "definition_tag2"]
+    );
+    assert!(d.terms[1].has_tag("term_tag_2"));
+    assert!(!d.terms[1].is_popular());
+    assert_eq!(d.kanji[0].tags, vec!["tag1", "tag2"]);
+    assert!(d.kanji[0].has_tag("tag2"));
+
+    // Stored records keep the space separated format
+    let json = serde_json::to_value(&d.terms[1]).unwrap();
+    assert_eq!(json["definition_tags"], "definition_tag1 definition_tag2");
+}
+
+#[test]
+fn test_parse_kanji_readings() {
+    let file = include_bytes!("dict_v1.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+    let kanji = &d.kanji[0];
+
+    assert_eq!(kanji.onyomi, vec!["ブン", "モン"]);
+    assert_eq!(kanji.kunyomi.len(), 2);
+    assert_eq!(kanji.kunyomi[1].stem, "き");
+    assert_eq!(kanji.kunyomi[1].okurigana, "こえる");
+    assert_eq!(kanji.kunyomi[1].full(), "きこえる");
+    assert_eq!(kanji.kunyomi[1].to_string(), "き.こえる");
+
+    let affix = KunReading::from("-あ.げる");
+    assert!(affix.suffix && !affix.prefix);
+    assert_eq!(affix.stem, "あ");
+    assert_eq!(affix.to_string(), "-あ.げる");
+}
+
+#[test]
+fn test_kanji_grouped_stats() {
+    let file = include_bytes!("dict.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+    let mut kanji = d.kanji[0].clone();
+    kanji.stats.insert("strokes".to_owned(), "14".to_owned());
+    kanji.stats.insert("jlpt".to_owned(), "2".to_owned());
+
+    let tag = |name: &str, category: &str, order: f32| Tag {
+        name: name.to_owned(),
+        category: category.to_owned(),
+        order,
+        notes: format!("{name} notes"),
+        score: 0.0,
+        dict_id: 0,
+    };
+    let tags = [
+        tag("jlpt", "class", 1.0),
+        tag("somestat2", "code", 2.0),
+        tag("somestat1", "code", 1.0),
+    ];
+
+    let groups = kanji.grouped_stats(&tags);
+
+    assert_eq!(groups.len(), 3);
+    assert_eq!(groups[0].category, StatCategory::Class);
+    assert_eq!(groups[0].stats[0].number, Some(2.0));
+    assert_eq!(groups[0].stats[0].notes, "jlpt notes");
+    assert_eq!(groups[1].category, StatCategory::Misc);
+    assert_eq!(groups[1].stats[0].name, "strokes");
+    assert_eq!(groups[1].stats[0].number, Some(14.0));
+    assert_eq!(groups[2].category, StatCategory::Code);
+    assert_eq!(groups[2].stats[0].name, "somestat1");
+    assert_eq!(groups[2].stats[1].value, "somevalue2");
+    assert_eq!(groups[2].stats[1].number, None);
+}
+
+#[test]
+fn test_parse_frequencies() {
+    let file = include_bytes!("dict_freq.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert_eq!(d.index.frequency_mode, Some(FrequencyMode::RankBased));
+    assert_eq!(
+        d.term_meta
+            .iter()
+            .filter(|m| matches!(m.data, TermMetaData::Frequency(_)))
+            .count(),
+        4
+    );
+
+    let kiku = &d.term_meta[0];
+    assert_eq!(kiku.reading.as_deref(), Some("きく"));
+    assert_eq!(
+        kiku.data,
+        TermMetaData::Frequency(Frequency {
+            value: 120.0,
+            display_value: Some("120㋕".to_owned())
+        })
+    );
+    assert!(kiku.applies_to("聞く", "きく"));
+    assert!(!kiku.applies_to("聞く", "ぶん"));
+
+    let kikoeru = &d.term_meta[2];
+    assert_eq!(kikoeru.reading, None);
+    assert_eq!(
+        kikoeru.data,
+        TermMetaData::Frequency(Frequency {
+            value: 850.0,
+            display_value: Some("850".to_owned())
+        })
+    );
+}
+
+#[test]
+fn test_parse_pitches() {
+    let file = include_bytes!("dict_pitch.zip");
+
+    let d = Dict::new(Cursor::new(file)).unwrap();
+
+    assert_eq!(d.term_meta.len(), 4);
+    assert_eq!(
+        d.term_meta[0].data,
+        TermMetaData::Pitch(vec![PitchAccent {
+            position: 0,
+            nasal: vec![],
+            devoice: vec![0],
+            tags: vec![],
+        }])
+    );
+
+    let TermMetaData::Pitch(kikoeru) = &d.term_meta[2].data else {
+        panic!("Expected pitch accents");
+    };
+    assert_eq!(kikoeru.len(), 2);
+    assert_eq!(kikoeru[0].position, 4);
+    assert_eq!(kikoeru[0].tags, vec!["odaka"]);
+
+    let TermMetaData::Pitch(kagami) = &d.term_meta[3].data else {
+        panic!("Expected pitch accents");
+    };
+    assert_eq!(kagami[0].nasal, vec![1]);
+}
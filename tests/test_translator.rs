@@ -2,7 +2,10 @@ use std::io::Cursor;
 
 use rexie::Rexie;
 use wasm_bindgen_test::wasm_bindgen_test;
-use yomi_dict::{inflection_reasons, Dict, IndexedDB, DB};
+use yomi_dict::{
+    inflection_reasons, Dict, FindTermsOptions, FuriganaSegment, IndexedDB, ResultMode, SearchMode,
+    SortCriterion, YomiDictError, DB,
+};
 
 async fn cleanup_db(name: &str) {
     Rexie::delete(name).await.unwrap();
@@ -26,6 +29,21 @@ async fn test_find_terms() {
     assert!(definitions
         .iter()
         .any(|d| d.entries.iter().any(|d| d.term.expression == "聞く")));
+
+    let def = definitions.iter().find(|d| d.expression == "聞く").unwrap();
+    assert_eq!(
+        def.furigana,
+        [
+            FuriganaSegment {
+                text: "聞".to_owned(),
+                reading: Some("き".to_owned())
+            },
+            FuriganaSegment {
+                text: "く".to_owned(),
+                reading: None
+            }
+        ]
+    );
 }
 
 #[wasm_bindgen_test]
@@ -51,6 +69,63 @@ async fn test_longest_deinflection() {
     assert_eq!(def.entries[0].source_len, 4);
 }
 
+#[wasm_bindgen_test]
+async fn test_source_ranges() {
+    cleanup_db("test_source_ranges").await;
+
+    let file = include_bytes!("dict.zip");
+
+    let dict = Dict::new(Cursor::new(file)).unwrap();
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_source_ranges").await.unwrap();
+
+    db.add_dict(dict).await.unwrap();
+
+    let definitions = db.find_terms("聞いたよ", &reasons).await.unwrap();
+    let def = definitions.iter().find(|d| d.expression == "聞く").unwrap();
+    assert_eq!(def.entries[0].source_chars, 0..3);
+    assert_eq!(def.entries[0].source_bytes, 0..9);
+
+    let definitions = db.find_terms("kiitayo", &reasons).await.unwrap();
+    let def = definitions.iter().find(|d| d.expression == "聞く").unwrap();
+    assert_eq!(def.entries[0].source_len, 5);
+    assert_eq!(def.entries[0].source_chars, 0..5);
+    assert_eq!(def.entries[0].source_bytes, 0..5);
+}
+
+#[wasm_bindgen_test]
+async fn test_parse_text() {
+    cleanup_db("test_parse_text").await;
+
+    let file = include_bytes!("dict.zip");
+
+    let dict = Dict::new(Cursor::new(file)).unwrap();
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_parse_text").await.unwrap();
+
+    db.add_dict(dict).await.unwrap();
+
+    let segments = db
+        .parse_text("聞いた、素早い", &reasons, &FindTermsOptions::default())
+        .await
+        .unwrap();
+
+    let texts = segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(texts, ["聞いた", "、", "素早い"]);
+
+    assert_eq!(segments[0].results[0].expression, "聞く");
+    assert!(segments[1].results.is_empty());
+    assert_eq!(segments[2].source_chars, 4..7);
+    assert_eq!(segments[2].source_bytes, 12..21);
+
+    let entry = &segments[2].results[0].entries[0];
+    assert_eq!(entry.term.expression, "素早い");
+    assert_eq!(entry.source_chars, 4..7);
+    assert_eq!(entry.source_bytes, 12..21);
+}
+
 #[wasm_bindgen_test]
 async fn test_no_duplicates() {
     cleanup_db("test_no_duplicates").await;
@@ -102,3 +177,265 @@ async fn test_multi_match() {
         .iter()
         .any(|d| d.entries.iter().any(|d| d.term.expression == "す速い")));
 }
+
+#[wasm_bindgen_test]
+async fn test_search_glossary() {
+    cleanup_db("test_search_glossary").await;
+
+    let file = include_bytes!("dict.zip");
+
+    let dict = Dict::new(Cursor::new(file)).unwrap();
+
+    let db = IndexedDB::new("test_search_glossary").await.unwrap();
+
+    db.add_dict(dict).await.unwrap();
+
+    let terms = db.search_glossary("to hear").await.unwrap();
+
+    assert_eq!(terms.first().unwrap().expression, "聞く");
+}
+
+#[wasm_bindgen_test]
+async fn test_search_terms() {
+    cleanup_db("test_search_terms").await;
+
+    let file = include_bytes!("dict.zip");
+
+    let dict = Dict::new(Cursor::new(file)).unwrap();
+
+    let db = IndexedDB::new("test_search_terms").await.unwrap();
+
+    db.add_dict(dict).await.unwrap();
+
+    let terms = db.search_terms("聞", SearchMode::Prefix).await.unwrap();
+    assert!(terms.iter().all(|t| t.expression.starts_with('聞')));
+    assert!(terms.iter().any(|t| t.expression == "聞く"));
+
+    let terms = db.search_terms("く", SearchMode::Suffix).await.unwrap();
+    assert!(terms.iter().any(|t| t.expression == "聞く"));
+
+    let terms = db.search_terms("聞?", SearchMode::Wildcard).await.unwrap();
+    assert!(terms.iter().any(|t| t.expression == "聞く"));
+    assert!(terms
+        .iter()
+        .all(|t| t.expression.chars().count() == 2 || t.reading.chars().count() == 2));
+
+    assert!(matches!(
+        db.search_terms("*聞*", SearchMode::Wildcard).await,
+        Err(YomiDictError::UnboundedPattern(_))
+    ));
+}
+
+#[wasm_bindgen_test]
+async fn test_find_terms_merged() {
+    cleanup_db("test_find_terms_merged").await;
+
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_find_terms_merged").await.unwrap();
+
+    let main = Dict::new(Cursor::new(include_bytes!("dict_sequenced.zip"))).unwrap();
+    db.add_dict(main).await.unwrap();
+    let secondary = Dict::new(Cursor::new(include_bytes!("dict.zip"))).unwrap();
+    db.add_dict(secondary).await.unwrap();
+
+    let definitions = db.find_terms_merged("聴いた", &reasons, 1).await.unwrap();
+
+    let def = definitions.first().unwrap();
+    assert_eq!(def.expression, "聴く");
+    assert_eq!(
+        def.forms,
+        vec![
+            ("聞く".to_owned(), "きく".to_owned()),
+            ("聴く".to_owned(), "きく".to_owned())
+        ]
+    );
+    // All senses of the sequence, then the secondary dictionary's definition of 聞く
+    assert_eq!(def.entries.len(), 4);
+    assert!(def.entries.iter().all(|e| e.source_len == 3));
+    assert_eq!(def.entries.last().unwrap().term.dict_id, 2);
+
+    let grouped = db.find_terms("聴いた", &reasons).await.unwrap();
+    assert_eq!(grouped.len(), 1);
+    assert_eq!(grouped[0].entries.len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn test_find_terms_merged_unsequenced() {
+    cleanup_db("test_find_terms_merged_unsequenced").await;
+
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_find_terms_merged_unsequenced")
+        .await
+        .unwrap();
+
+    let main = Dict::new(Cursor::new(include_bytes!("dict_modern.zip"))).unwrap();
+    db.add_dict(main).await.unwrap();
+
+    let merged = db.find_terms_merged("すばやい", &reasons, 1).await.unwrap();
+    let grouped = db.find_terms("すばやい", &reasons).await.unwrap();
+
+    assert_eq!(merged.len(), grouped.len());
+    for (m, g) in merged.iter().zip(&grouped) {
+        assert_eq!(m.forms, g.forms);
+        assert_eq!(m.entries.len(), g.entries.len());
+    }
+}
+
+#[wasm_bindgen_test]
+async fn test_find_terms_split() {
+    cleanup_db("test_find_terms_split").await;
+
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_find_terms_split").await.unwrap();
+
+    let dict = Dict::new(Cursor::new(include_bytes!("dict_sequenced.zip"))).unwrap();
+    db.add_dict(dict).await.unwrap();
+
+    let options = FindTermsOptions {
+        mode: ResultMode::Split,
+        ..Default::default()
+    };
+    let definitions = db
+        .find_terms_with_options("聞いた", &reasons, &options)
+        .await
+        .unwrap();
+
+    assert_eq!(definitions.len(), 2);
+    assert!(definitions.iter().all(|d| d.entries.len() == 1));
+    assert_eq!(definitions[0].entries[0].term.glossary[0], "to hear");
+    assert_eq!(definitions[1].entries[0].term.glossary[0], "to ask");
+}
+
+#[wasm_bindgen_test]
+async fn test_find_terms_options() {
+    cleanup_db("test_find_terms_options").await;
+
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_find_terms_options").await.unwrap();
+
+    let main = Dict::new(Cursor::new(include_bytes!("dict_sequenced.zip"))).unwrap();
+    db.add_dict(main).await.unwrap();
+    let secondary = Dict::new(Cursor::new(include_bytes!("dict.zip"))).unwrap();
+    db.add_dict(secondary).await.unwrap();
+
+    let find = |options: FindTermsOptions| {
+        let db = &db;
+        let reasons = &reasons;
+        async move {
+            db.find_terms_with_options("聞いた", reasons, &options)
+                .await
+                .unwrap()
+        }
+    };
+
+    let split = find(FindTermsOptions {
+        mode: ResultMode::Split,
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(split.len(), 3);
+
+    let limited = find(FindTermsOptions {
+        mode: ResultMode::Split,
+        max_results: Some(2),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(limited.len(), 2);
+
+    let filtered = find(FindTermsOptions {
+        dictionaries: Some(vec![2]),
+        ..Default::default()
+    })
+    .await;
+    assert!(filtered
+        .iter()
+        .all(|d| d.entries.iter().all(|e| e.term.dict_id == 2)));
+
+    let not_deinflected = find(FindTermsOptions {
+        deinflect: false,
+        ..Default::default()
+    })
+    .await;
+    assert!(not_deinflected.is_empty());
+}
+
+#[wasm_bindgen_test]
+async fn test_find_terms_frequencies() {
+    cleanup_db("test_find_terms_frequencies").await;
+
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_find_terms_frequencies").await.unwrap();
+
+    let main = Dict::new(Cursor::new(include_bytes!("dict_sequenced.zip"))).unwrap();
+    db.add_dict(main).await.unwrap();
+    let freq = Dict::new(Cursor::new(include_bytes!("dict_freq.zip"))).unwrap();
+    db.add_dict(freq).await.unwrap();
+
+    let options = FindTermsOptions {
+        mode: ResultMode::Merge { main_dict: 1 },
+        ..Default::default()
+    };
+    let definitions = db
+        .find_terms_with_options("聴く", &reasons, &options)
+        .await
+        .unwrap();
+
+    let def = definitions.first().unwrap();
+    // One for each form; the frequency of the kana spelling きく belongs to a different expression
+    assert_eq!(def.frequencies.len(), 2);
+    assert!(def.frequencies.iter().all(|f| f.dict_id == 2));
+
+    let options = FindTermsOptions {
+        sort_by: vec![SortCriterion::Frequency(2)],
+        ..Default::default()
+    };
+    let definitions = db
+        .find_terms_with_options("きこえる", &reasons, &options)
+        .await
+        .unwrap();
+    assert_eq!(definitions.first().unwrap().expression, "聞こえる");
+
+    // Only the frequencies of the selected dictionaries are looked up
+    let options = FindTermsOptions {
+        mode: ResultMode::Merge { main_dict: 1 },
+        dictionaries: Some(vec![1]),
+        ..Default::default()
+    };
+    let definitions = db
+        .find_terms_with_options("聴く", &reasons, &options)
+        .await
+        .unwrap();
+    assert!(definitions.first().unwrap().frequencies.is_empty());
+
+    // What the database records about a dictionary isn't part of its index
+    let dictionaries = db.find_dictionaries().await.unwrap();
+    assert!(dictionaries.iter().all(|(_, index)| index.extra.is_empty()));
+}
+
+#[wasm_bindgen_test]
+async fn test_find_terms_pitches() {
+    cleanup_db("test_find_terms_pitches").await;
+
+    let reasons = inflection_reasons();
+
+    let db = IndexedDB::new("test_find_terms_pitches").await.unwrap();
+
+    let main = Dict::new(Cursor::new(include_bytes!("dict_sequenced.zip"))).unwrap();
+    db.add_dict(main).await.unwrap();
+    let pitch = Dict::new(Cursor::new(include_bytes!("dict_pitch.zip"))).unwrap();
+    db.add_dict(pitch).await.unwrap();
+
+    let definitions = db.find_terms("聞こえた", &reasons).await.unwrap();
+
+    let def = definitions.first().unwrap();
+    assert_eq!(def.expression, "聞こえる");
+    assert_eq!(def.pitches.len(), 1);
+    assert_eq!(def.pitches[0].dict_id, 2);
+    assert_eq!(def.pitches[0].pitches.len(), 2);
+}